@@ -80,6 +80,7 @@ chmod +x "${DEPLOY_TMP}/start_client.sh"
 # Copy necessary files
 cp target/x86_64-unknown-linux-gnu/release/server "${DEPLOY_TMP}/"
 cp -r www/* "${DEPLOY_TMP}/www/"
+cp -r maps "${DEPLOY_TMP}/"
 
 echo "==> Deploying to ${TARGET_SERVER}..."
 # Ensure remote directory exists
@@ -0,0 +1,107 @@
+#[cfg(target_arch = "wasm32")]
+use super::game::Game;
+#[cfg(target_arch = "wasm32")]
+use crate::shared::{
+    cb_packet::{ChatMessage, ChatRejectReason},
+    sb_packet::{Chat, ChatScope},
+    SBPacket,
+};
+
+/// Index of the chat input in the game's text inputs
+#[cfg(target_arch = "wasm32")]
+pub const CHAT_INPUT: usize = 2;
+
+/// Messages kept in the chat history
+#[cfg(target_arch = "wasm32")]
+const MAX_CHAT_LINES: usize = 50;
+
+/// Messages drawn above the chat input
+#[cfg(target_arch = "wasm32")]
+const VISIBLE_CHAT_LINES: usize = 8;
+
+#[cfg(target_arch = "wasm32")]
+pub struct ChatLine {
+    pub text: String,
+    pub color: &'static str,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Game {
+    pub fn push_chat_line(&self, text: String, color: &'static str) {
+        let mut chat = self.chat.lock();
+        chat.push(ChatLine { text, color });
+        if chat.len() > MAX_CHAT_LINES {
+            chat.remove(0);
+        }
+    }
+
+    /// Sends what was typed into the chat input. Starting with "/t " only reaches the team.
+    pub fn send_chat(&self) {
+        let text = match self.text_inputs.lock().get_mut(CHAT_INPUT) {
+            Some(input) => std::mem::take(&mut input.text),
+            None => return,
+        };
+        if text.trim().is_empty() {
+            return;
+        }
+
+        let (scope, text) = if self.current_room.lock().is_none() {
+            (ChatScope::Lobby, text)
+        } else if let Some(team_text) = text.strip_prefix("/t ") {
+            (ChatScope::Team, team_text.to_string())
+        } else {
+            (ChatScope::All, text)
+        };
+
+        if let Ok(bytes) = bincode::serialize(&SBPacket::Chat(Chat { scope, text })) {
+            self.websocket.lock().send_binary(bytes);
+        }
+    }
+
+    pub fn handle_chat_message(&self, message: ChatMessage) {
+        let (text, color) = match message.scope {
+            ChatScope::Team => (format!("[Team] {}: {}", message.name, message.text), "#80c0ff"),
+            ChatScope::Lobby | ChatScope::All => (format!("{}: {}", message.name, message.text), "white"),
+        };
+        self.push_chat_line(text, color);
+    }
+
+    pub fn handle_chat_rejected(&self, reason: ChatRejectReason) {
+        let text = match reason {
+            ChatRejectReason::Empty => "Message is empty",
+            ChatRejectReason::TooLong => "Message is too long",
+            ChatRejectReason::RateLimited => "You are sending messages too quickly",
+            ChatRejectReason::NoAudience => "Nobody can receive that message",
+        };
+        self.push_chat_line(text.to_string(), "#ff8080");
+    }
+
+    /// Draws the latest messages above the chat input, and the input itself.
+    pub fn render_chat(&self, context: &web_sys::CanvasRenderingContext2d) {
+        let text_inputs = self.text_inputs.lock();
+        let Some(input) = text_inputs.get(CHAT_INPUT) else {
+            return;
+        };
+
+        let chat = self.chat.lock();
+        let line_height = 20.0;
+        let lines: Vec<&ChatLine> = chat.iter().rev().take(VISIBLE_CHAT_LINES).collect();
+        if !lines.is_empty() {
+            let height = lines.len() as f64 * line_height + 10.0;
+            context.set_fill_style_str("rgba(0, 0, 0, 0.5)");
+            context.fill_rect(input.x, input.y - height - 5.0, input.width, height);
+        }
+
+        context.set_font("14px Arial");
+        context.set_text_align("left");
+        context.set_text_baseline("middle");
+        // Newest message right above the input
+        for (i, line) in lines.iter().enumerate() {
+            context.set_fill_style_str(line.color);
+            let y = input.y - 10.0 - line_height / 2.0 - i as f64 * line_height;
+            let _ = context.fill_text_with_max_width(&line.text, input.x + 5.0, y, input.width - 10.0);
+        }
+
+        input.render(context);
+    }
+}
@@ -0,0 +1,266 @@
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::{JsCast, JsValue};
+
+#[cfg(target_arch = "wasm32")]
+use super::game::Game;
+#[cfg(target_arch = "wasm32")]
+use crate::shared::{
+    map::{Cell, MapView},
+    map_file::{MapFile, DEFAULT_CITY_TROOPS},
+    sb_packet::UploadMap,
+    SBPacket,
+    Terrain,
+};
+
+/// Brushes picked with the number keys, capitals stand for spawn slots
+#[cfg(target_arch = "wasm32")]
+const BRUSHES: [(Terrain, &str); 6] = [
+    (Terrain::Default, "Plain"),
+    (Terrain::Mountain, "Mountain"),
+    (Terrain::Desert, "Desert"),
+    (Terrain::Swamp, "Swamp"),
+    (Terrain::City, "City"),
+    (Terrain::Capital, "Spawn"),
+];
+
+/// Troops added or taken from the city garrison with + and -
+#[cfg(target_arch = "wasm32")]
+const TROOP_STEP: u32 = 5;
+
+#[cfg(target_arch = "wasm32")]
+const MAX_CITY_TROOPS: u32 = 500;
+
+/// Size of the map the editor starts with
+#[cfg(target_arch = "wasm32")]
+const START_SIZE: usize = 20;
+
+#[cfg(target_arch = "wasm32")]
+pub struct EditorState {
+    pub brush: usize,                            // Index into BRUSHES
+    pub city_troops: u32,                        // Garrison of the cities being painted
+    pub name: Option<String>,                    // Name the map was last exported or uploaded as
+    pub status: Option<(String, &'static str)>,  // Outcome of the last export or upload, with its color
+}
+
+#[cfg(target_arch = "wasm32")]
+fn blank_map(width: usize, height: usize) -> MapView {
+    let cells = (0..width * height)
+        .map(|id| (id, Cell { terrain: Terrain::Default, troops: 0, owner_id: None, fog_of_war: false }))
+        .collect();
+    MapView { width, height, cells }
+}
+
+/// Offers `contents` as a file download.
+#[cfg(target_arch = "wasm32")]
+fn download(file_name: &str, contents: &str) -> Result<(), JsValue> {
+    let parts = js_sys::Array::of1(&JsValue::from_str(contents));
+    let options = web_sys::BlobPropertyBag::new();
+    options.set_type("application/toml");
+    let blob = web_sys::Blob::new_with_str_sequence_and_options(&parts, &options)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob)?;
+
+    let document = web_sys::window().unwrap().document().unwrap();
+    let anchor = document.create_element("a")?.dyn_into::<web_sys::HtmlAnchorElement>()?;
+    anchor.set_href(&url);
+    anchor.set_download(file_name);
+    anchor.click();
+    web_sys::Url::revoke_object_url(&url)
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Game {
+    /// Opens the editor on a blank map.
+    pub fn open_editor(&self) {
+        *self.editor.lock() = Some(EditorState {
+            brush: 1,
+            city_troops: DEFAULT_CITY_TROOPS,
+            name: None,
+            status: None,
+        });
+        *self.selected_cell.lock() = None;
+        let bounds = self.settings_bounds.lock().clone();
+        let size = START_SIZE.clamp(bounds.min_map_size, bounds.max_map_size);
+        self.replace_map(blank_map(size, size));
+    }
+
+    pub fn close_editor(&self) {
+        self.editor.lock().take();
+        self.map.lock().take();
+    }
+
+    /// Paints the tile under the cursor with the current brush. Returns true if a tile was hit.
+    pub fn paint_at(&self, x: f64, y: f64) -> bool {
+        let Some((terrain, troops)) = self.editor.lock().as_ref()
+            .map(|editor| (BRUSHES[editor.brush].0, editor.city_troops)) else {
+            return false;
+        };
+        let Some(cell_id) = self.get_cell_at_position(x, y) else {
+            return false;
+        };
+
+        if let Some(map) = self.map.lock().as_mut()
+            && let Some(cell) = map.cells.get_mut(&cell_id) {
+            cell.terrain = terrain;
+            cell.troops = if terrain == Terrain::City { troops } else { 0 };
+        }
+        true
+    }
+
+    /// Grows or shrinks the map from the bottom right, keeping the tiles that still fit,
+    /// within the map sizes the server accepts.
+    fn resize_editor_map(&self, width_change: isize, height_change: isize) {
+        let bounds = self.settings_bounds.lock().clone();
+        let mut map_guard = self.map.lock();
+        let Some(map) = map_guard.as_mut() else {
+            return;
+        };
+
+        let resize = |size: usize, change: isize| {
+            size.saturating_add_signed(change).clamp(bounds.min_map_size, bounds.max_map_size)
+        };
+        let mut resized = blank_map(resize(map.width, width_change), resize(map.height, height_change));
+        for y in 0..resized.height.min(map.height) {
+            for x in 0..resized.width.min(map.width) {
+                if let Some(cell) = map.cells.remove(&(y * map.width + x)) {
+                    resized.cells.insert(y * resized.width + x, cell);
+                }
+            }
+        }
+        *map = resized;
+    }
+
+    /// Asks for the name to save the map under, suggesting the last one used.
+    fn ask_map_name(&self) -> Option<String> {
+        let last = self.editor.lock().as_ref().and_then(|editor| editor.name.clone()).unwrap_or_default();
+        let name = web_sys::window()?
+            .prompt_with_message_and_default("Map name (letters, digits, - and _)", &last)
+            .ok()??;
+        let name = name.trim().to_string();
+        if name.is_empty() {
+            return None;
+        }
+        if let Some(editor) = self.editor.lock().as_mut() {
+            editor.name = Some(name.clone());
+        }
+        Some(name)
+    }
+
+    fn set_editor_status(&self, text: String, color: &'static str) {
+        if let Some(editor) = self.editor.lock().as_mut() {
+            editor.status = Some((text, color));
+        }
+    }
+
+    /// Downloads the map in the server's map file format.
+    fn export_map(&self) {
+        let Some(file) = self.map.lock().as_ref().map(MapFile::from_view) else {
+            return;
+        };
+        let Some(name) = self.ask_map_name() else {
+            return;
+        };
+
+        match download(&format!("{name}.toml"), &file.to_toml()) {
+            Ok(()) => self.set_editor_status(format!("Exported {name}.toml"), "#4CAF50"),
+            Err(e) => {
+                web_sys::console::error_1(&e);
+                self.set_editor_status("Export failed".to_string(), "#ff8080");
+            }
+        }
+    }
+
+    /// Sends the map to the server, which opens a room to play it in.
+    fn upload_map(&self) {
+        let Some(map) = self.map.lock().as_ref().map(MapFile::from_view) else {
+            return;
+        };
+        let Some(name) = self.ask_map_name() else {
+            return;
+        };
+
+        if let Ok(bytes) = bincode::serialize(&SBPacket::UploadMap(UploadMap { name: name.clone(), map })) {
+            self.websocket.lock().send_binary(bytes);
+            self.set_editor_status(format!("Uploading {name}..."), "white");
+        }
+    }
+
+    pub fn handle_map_uploaded(&self, name: String) {
+        self.close_editor();
+        self.push_chat_line(format!("Map {name} uploaded"), "#4CAF50");
+    }
+
+    pub fn handle_map_rejected(&self, reason: String) {
+        self.set_editor_status(format!("Upload failed: {reason}"), "#ff8080");
+    }
+
+    /// Handles a key press while the editor is open. Returns true if the key was used.
+    pub fn handle_editor_key(&self, key: &str) -> bool {
+        match key {
+            "1" | "2" | "3" | "4" | "5" | "6" => {
+                if let Some(editor) = self.editor.lock().as_mut() {
+                    editor.brush = key.parse::<usize>().unwrap_or(1) - 1;
+                }
+            }
+            "+" | "=" | "-" => {
+                if let Some(editor) = self.editor.lock().as_mut() {
+                    editor.city_troops = if key == "-" {
+                        editor.city_troops.saturating_sub(TROOP_STEP)
+                    } else {
+                        (editor.city_troops + TROOP_STEP).min(MAX_CITY_TROOPS)
+                    };
+                }
+            }
+            "arrowleft" => self.resize_editor_map(-1, 0),
+            "arrowright" => self.resize_editor_map(1, 0),
+            "arrowup" => self.resize_editor_map(0, -1),
+            "arrowdown" => self.resize_editor_map(0, 1),
+            "e" => self.export_map(),
+            "u" => self.upload_map(),
+            "escape" => self.close_editor(),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Shows the brush, the map size and what the keys do below the map.
+    pub fn render_editor_hud(&self, context: &web_sys::CanvasRenderingContext2d, width: f64, height: f64) {
+        let editor_guard = self.editor.lock();
+        let Some(editor) = editor_guard.as_ref() else {
+            return;
+        };
+        let (width_tiles, height_tiles) = self.map.lock().as_ref().map_or((0, 0), |map| (map.width, map.height));
+
+        let dpr = web_sys::window().unwrap().device_pixel_ratio();
+        let logical_width = width / dpr;
+        let logical_height = height / dpr;
+
+        let (_, brush_name) = BRUSHES[editor.brush];
+        let brush = if BRUSHES[editor.brush].0 == Terrain::City {
+            format!("{brush_name} ({} troops)", editor.city_troops)
+        } else {
+            brush_name.to_string()
+        };
+
+        context.set_font("16px Arial");
+        context.set_text_align("center");
+        context.set_text_baseline("middle");
+        context.set_fill_style_str("white");
+        let _ = context.fill_text(
+            &format!("Map editor {width_tiles}x{height_tiles}, brush: {brush}"),
+            logical_width / 2.0,
+            25.0,
+        );
+        if let Some((status, color)) = &editor.status {
+            context.set_fill_style_str(color);
+            let _ = context.fill_text(status, logical_width / 2.0, 45.0);
+        }
+
+        context.set_font("14px Arial");
+        context.set_fill_style_str("#a0a0a0");
+        let _ = context.fill_text(
+            "1-6 plain/mountain/desert/swamp/city/spawn, +/- city troops, arrows resize, E export, U upload and play, Esc leave",
+            logical_width / 2.0,
+            logical_height - 25.0,
+        );
+    }
+}
@@ -14,38 +14,62 @@ use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement};
 use crate::{
     shared::{
         game_state::GameState,
-        path::Path,
+        path::{Path, PathRejectReason, SplitMode},
         map::MapView,
         PlayerView,
+        RoomView,
         SBPacket,
-        sb_packet::{UpdatePaths, Login},
+        cb_packet::RoomSettings,
+        settings::SettingsBounds,
+        sb_packet::{UpdatePaths, Login, JoinRoom, FollowPlayer, WatchReplay, KickPlayer, TransferHost},
     },
     client::{
         websocket::WebSocketClient,
         button::Button,
         text_input::TextInput,
+        replay::ReplayState,
+        editor::EditorState,
+        chat::{ChatLine, CHAT_INPUT},
     },
 };
 
 
 #[cfg(target_arch = "wasm32")]
 use parking_lot::Mutex;
+#[cfg(target_arch = "wasm32")]
+use uuid::Uuid;
 
 #[cfg(target_arch = "wasm32")]
 pub struct Game {
     pub map: Mutex<Option<MapView>>,
+    pub map_tick: Mutex<Option<u32>>,  // Number of the last map update applied, None while waiting for a snapshot
     pub canvas: Mutex<HtmlCanvasElement>,
     pub context: Mutex<CanvasRenderingContext2d>,
     pub selected_cell: Mutex<Option<usize>>,
     pub selected_path: Mutex<Option<u32>>,  // ID of the currently selected path
     pub players: Mutex<Vec<PlayerView>>,
+    pub host: Mutex<Option<Uuid>>,  // Player running the current room's lobby
+    pub player_id: Mutex<Option<Uuid>>,
+    pub following: Mutex<Option<Uuid>>,  // Player a spectator is watching, full map if None
     pub paths: Mutex<HashMap<u32, Mutex<Path>>>,
+    pub split_mode: Mutex<SplitMode>,  // Split used for new paths
     pub next_path_id: Mutex<u32>,
     pub websocket: Rc<Mutex<WebSocketClient>>,
     pub game_state: Mutex<GameState>,
     pub buttons: Mutex<Vec<Button>>,
     pub text_inputs: Mutex<Vec<TextInput>>,
     pub player_name: Mutex<String>,
+    pub room_name: Mutex<String>,
+    pub rooms: Mutex<Vec<RoomView>>,
+    pub current_room: Mutex<Option<String>>,
+    pub seed: Mutex<Option<u64>>,  // Seed of the current room's map
+    pub room_settings: Mutex<Option<RoomSettings>>,  // Settings of the current room's next game
+    pub settings_bounds: Mutex<SettingsBounds>,  // Server's latest limits on settings and map sizes
+    pub replays: Mutex<Vec<String>>,
+    pub show_replays: Mutex<bool>,  // Room browser lists replays instead of rooms
+    pub replay: Mutex<Option<ReplayState>>,
+    pub editor: Mutex<Option<EditorState>>,  // Map editor, shown instead of the lobby while open
+    pub chat: Mutex<Vec<ChatLine>>,  // Received chat messages, oldest first
     pub connected: Mutex<bool>,
     pub p_press_count: Mutex<u32>,
     pub last_p_press: Mutex<f64>,
@@ -122,18 +146,34 @@ impl Game {
 
         let game = Rc::new(Game {
                 map: Mutex::new(None),
+                map_tick: Mutex::new(None),
                 canvas: Mutex::new(canvas),
                 context: Mutex::new(context),
                 selected_cell: Mutex::new(None),
                 selected_path: Mutex::new(None),
                 players: Mutex::new(vec![]),
+                host: Mutex::new(None),
+                player_id: Mutex::new(None),
+                following: Mutex::new(None),
                 paths: Mutex::new(HashMap::new()),
+                split_mode: Mutex::new(SplitMode::AllButOne),
                 next_path_id: Mutex::new(0),
                 websocket: websocket.clone(),
                 game_state: Mutex::new(GameState::Lobby),
                 buttons: Mutex::new(Vec::new()),
                 text_inputs: Mutex::new(Vec::new()),
                 player_name: Mutex::new(String::new()),
+                room_name: Mutex::new(String::new()),
+                rooms: Mutex::new(Vec::new()),
+                current_room: Mutex::new(None),
+                seed: Mutex::new(None),
+                room_settings: Mutex::new(None),
+                settings_bounds: Mutex::new(SettingsBounds::default()),
+                replays: Mutex::new(Vec::new()),
+                show_replays: Mutex::new(false),
+                replay: Mutex::new(None),
+                editor: Mutex::new(None),
+                chat: Mutex::new(Vec::new()),
                 connected: Mutex::new(false),
                 p_press_count: Mutex::new(0),
                 last_p_press: Mutex::new(0.0),
@@ -145,10 +185,13 @@ impl Game {
         game.buttons.lock().extend(buttons);
         game.text_inputs.lock().extend(text_inputs);
 
-            // Disable start button until name is entered
+            // Disable join and create buttons until a name is entered
             if let Some(start_button) = game.buttons.lock().get_mut(0) {
                 start_button.enabled = false;
             }
+            if let Some(create_button) = game.buttons.lock().get_mut(2) {
+                create_button.enabled = false;
+            }
 
         Ok(game)
     }
@@ -161,6 +204,122 @@ impl Game {
         &self.context
     }
 
+    pub fn logical_size(&self) -> (f64, f64) {
+        let canvas = self.canvas.lock();
+        let dpr = web_sys::window().unwrap().device_pixel_ratio();
+        (canvas.width() as f64 / dpr, canvas.height() as f64 / dpr)
+    }
+
+
+    pub fn is_spectator(&self) -> bool {
+        let player_id = *self.player_id.lock();
+        self.players.lock().iter().any(|p| Some(p.id) == player_id && p.spectator)
+    }
+
+    pub fn own_team(&self) -> Option<u8> {
+        let player_id = *self.player_id.lock();
+        self.players.lock().iter().find(|p| Some(p.id) == player_id).and_then(|p| p.team)
+    }
+
+    pub fn is_host(&self) -> bool {
+        let player_id = *self.player_id.lock();
+        player_id.is_some() && *self.host.lock() == player_id
+    }
+
+    pub fn is_ready(&self) -> bool {
+        let player_id = *self.player_id.lock();
+        self.players.lock().iter().any(|p| Some(p.id) == player_id && p.ready)
+    }
+
+    /// Relabels the spectate toggle, team picker and ready toggle after the player list changes.
+    /// Only the host gets to press start.
+    pub fn update_spectator_button(&self) {
+        let spectator = self.is_spectator();
+        let team = self.own_team();
+        let ready = self.is_ready();
+        let host = self.is_host();
+        let mut buttons = self.buttons.lock();
+        if let Some(button) = buttons.get_mut(1) {
+            button.enabled = host;
+        }
+        if let Some(button) = buttons.get_mut(3) {
+            button.text = if spectator { "Play" } else { "Spectate" }.to_string();
+        }
+        if let Some(button) = buttons.get_mut(8) {
+            button.text = match team {
+                Some(team) => format!("Team: {team}"),
+                None => "Team: none".to_string(),
+            };
+        }
+        if let Some(button) = buttons.get_mut(9) {
+            button.text = if ready { "Not Ready" } else { "Ready" }.to_string();
+            button.enabled = !spectator;
+        }
+    }
+
+    /// Takes the player back to the room list after the host removed them.
+    pub fn handle_kicked(&self) {
+        self.clear_room();
+        self.push_chat_line("You were kicked from the room".to_string(), "#ff8080");
+    }
+
+    /// Leaves the current room for the room list, which the server sends once the player is out.
+    pub fn leave_room(&self) {
+        if let Ok(bytes) = bincode::serialize(&SBPacket::LeaveRoom) {
+            self.websocket.lock().send_binary(bytes);
+        }
+        self.clear_room();
+    }
+
+    /// Forgets everything about the room the player was in.
+    fn clear_room(&self) {
+        *self.current_room.lock() = None;
+        *self.host.lock() = None;
+        *self.room_settings.lock() = None;
+        *self.game_state.lock() = GameState::Lobby;
+        self.map.lock().take();
+        self.paths.lock().clear();
+        *self.selected_path.lock() = None;
+        *self.selected_cell.lock() = None;
+    }
+
+    /// Switches a spectator to the next player's perspective, then back to the full map.
+    pub fn cycle_follow(&self) {
+        if !self.is_spectator() {
+            return;
+        }
+
+        let candidates: Vec<Uuid> = self.players.lock().iter()
+            .filter(|p| !p.spectator && p.alive)
+            .map(|p| p.id)
+            .collect();
+        let mut following = self.following.lock();
+        let next = match *following {
+            None => candidates.first().copied(),
+            Some(current) => candidates.iter()
+                .position(|&id| id == current)
+                .and_then(|i| candidates.get(i + 1).copied()),
+        };
+        *following = next;
+
+        if let Ok(bytes) = bincode::serialize(&SBPacket::FollowPlayer(FollowPlayer { player_id: next })) {
+            self.websocket.lock().send_binary(bytes);
+        }
+    }
+
+    /// Swaps in a full map from the server. Paths and the selection point at cells
+    /// of the old map, so they are dropped when the size changes.
+    pub fn replace_map(&self, map: MapView) {
+        let resized = self.map.lock().as_ref()
+            .is_some_and(|old| old.width != map.width || old.height != map.height);
+        if resized {
+            log::info!("Map size changed to {}x{}", map.width, map.height);
+            self.paths.lock().clear();
+            *self.selected_path.lock() = None;
+            *self.selected_cell.lock() = None;
+        }
+        self.map.lock().replace(map);
+    }
 
     pub fn handle_movement_confirmed(&self, path_id: u32, valid_until: u32) {
         let paths = self.paths.lock();
@@ -170,16 +329,48 @@ impl Game {
         }
     }
 
+    pub fn handle_path_rejected(&self, path_id: u32, reason: PathRejectReason) {
+        log::info!("Server rejected path {path_id}: {reason:?}");
+        self.paths.lock().remove(&path_id);
+        let mut selected_path = self.selected_path.lock();
+        if *selected_path == Some(path_id) {
+            *selected_path = None;
+        }
+    }
+
+    pub fn focus_chat(&self) {
+        let mut text_inputs = self.text_inputs.lock();
+        for (i, input) in text_inputs.iter_mut().enumerate() {
+            input.focused = i == CHAT_INPUT;
+        }
+    }
+
     pub fn handle_key(&self, key: &str) {
-        // Only handle text input in lobby
+        // The chat input works on every screen, Enter sends and Escape leaves it
+        let mut text_inputs = self.text_inputs.lock();
+        if let Some(chat) = text_inputs.get_mut(CHAT_INPUT)
+            && chat.focused {
+            match key {
+                "Enter" => {
+                    chat.focused = false;
+                    drop(text_inputs);
+                    self.send_chat();
+                }
+                "Escape" => chat.focused = false,
+                _ => chat.handle_key(key),
+            }
+            return;
+        }
+
+        // Only handle the other text inputs in lobby
         if *self.game_state.lock() == GameState::Lobby {
-            let mut text_inputs = self.text_inputs.lock();
-            for input in text_inputs.iter_mut() {
+            for (i, input) in text_inputs.iter_mut().enumerate().take(CHAT_INPUT) {
                 input.handle_key(key);
                 if input.focused {
-                    // Enable/disable start button based on name
-                    if let Some(start_button) = self.buttons.lock().get_mut(0) {
-                        start_button.enabled = !input.text.trim().is_empty();
+                    // Name input enables the join button, room input enables the create button
+                    let button_index = if i == 0 { 0 } else { 2 };
+                    if let Some(button) = self.buttons.lock().get_mut(button_index) {
+                        button.enabled = !input.text.trim().is_empty();
                     }
                 }
             }
@@ -187,37 +378,129 @@ impl Game {
     }
 
     pub fn handle_click(&self, client_x: f64, client_y: f64) {
+        // Replays only react to the progress bar
+        if self.replay.lock().is_some() {
+            self.handle_replay_click(client_x, client_y);
+            return;
+        }
+
+        // The editor paints the clicked tile and ignores everything else
+        if self.editor.lock().is_some() {
+            self.paint_at(client_x, client_y);
+            return;
+        }
+
+        // The chat input is shown on every screen once connected
+        if *self.connected.lock()
+            && let Some(chat) = self.text_inputs.lock().get_mut(CHAT_INPUT)
+            && chat.handle_click(client_x, client_y) {
+            return;
+        }
+
+        // The host can kick anyone else from the player list, or hand the role to another human
+        if self.is_host() && self.current_room.lock().is_some() {
+            let player_id = *self.player_id.lock();
+            let listed: Vec<(Uuid, bool)> = self.players.lock().iter()
+                .filter(|p| !p.spectator)
+                .map(|p| (p.id, p.bot.is_some()))
+                .collect();
+            let hit = |(x, y, width, height): (f64, f64, f64, f64)| {
+                client_x >= x && client_x <= x + width && client_y >= y && client_y <= y + height
+            };
+            for (i, (id, bot)) in listed.into_iter().enumerate() {
+                if Some(id) == player_id {
+                    continue;
+                }
+                let packet = if hit(crate::client::ui::kick_button_bounds(i)) {
+                    SBPacket::KickPlayer(KickPlayer { player_id: id })
+                } else if !bot && hit(crate::client::ui::host_button_bounds(i)) {
+                    SBPacket::TransferHost(TransferHost { player_id: id })
+                } else {
+                    continue;
+                };
+                if let Ok(bytes) = bincode::serialize(&packet) {
+                    self.websocket.lock().send_binary(bytes);
+                }
+                return;
+            }
+        }
+
         // Only check UI elements in lobby
         if *self.game_state.lock() == GameState::Lobby {
-            // Check text inputs first
+            let connected = *self.connected.lock();
+            let in_room = self.current_room.lock().is_some();
+
+            // Only the input for the current screen can take focus
+            let show_replays = *self.show_replays.lock();
+            let visible_input = match (connected, in_room) {
+                (false, _) => Some(0),
+                (true, false) if !show_replays => Some(1),
+                _ => None,
+            };
             let mut text_inputs = self.text_inputs.lock();
-            for input in text_inputs.iter_mut() {
-                if input.handle_click(client_x, client_y) {
-                    return;
+            for (i, input) in text_inputs.iter_mut().enumerate().take(CHAT_INPUT) {
+                if Some(i) == visible_input {
+                    if input.handle_click(client_x, client_y) {
+                        return;
+                    }
+                } else {
+                    input.focused = false;
                 }
             }
             drop(text_inputs);
 
-        // Check only the visible button based on connection state
-        let buttons = self.buttons.lock();
-        if !*self.connected.lock() {
-            // Only check Join button (first button) when not connected
-            if let Some(button) = buttons.get(0) {
-                if button.enabled && button.contains(client_x, client_y) {
-                    (button.callback)();
-                    return;
+            // Check replay list entries when browsing replays
+            if connected && !in_room && show_replays {
+                let (logical_width, logical_height) = self.logical_size();
+                let replays = self.replays.lock();
+                for (i, name) in replays.iter().enumerate().take(crate::client::ui::MAX_ROOM_ROWS) {
+                    let (x, y, width, height) = crate::client::ui::room_row_bounds(i, logical_width, logical_height);
+                    if client_x >= x && client_x <= x + width && client_y >= y && client_y <= y + height {
+                        if let Ok(bytes) = bincode::serialize(&SBPacket::WatchReplay(WatchReplay { name: name.clone() })) {
+                            self.websocket.lock().send_binary(bytes);
+                        }
+                        return;
+                    }
+                }
+            }
+
+            // Check room list entries when choosing a room
+            if connected && !in_room && !show_replays {
+                let (logical_width, logical_height) = self.logical_size();
+                let rooms = self.rooms.lock();
+                for (i, room) in rooms.iter().enumerate().take(crate::client::ui::MAX_ROOM_ROWS) {
+                    let (x, y, width, height) = crate::client::ui::room_row_bounds(i, logical_width, logical_height);
+                    if client_x >= x && client_x <= x + width && client_y >= y && client_y <= y + height {
+                        // The right end of each row joins as a spectator
+                        let (watch_x, ..) = crate::client::ui::room_watch_bounds(i, logical_width, logical_height);
+                        let join = JoinRoom { name: room.name.clone(), spectate: client_x >= watch_x };
+                        if let Ok(bytes) = bincode::serialize(&SBPacket::JoinRoom(join)) {
+                            self.websocket.lock().send_binary(bytes);
+                        }
+                        return;
+                    }
                 }
             }
-        } else {
-            // Only check Start button (second button) when connected
-            if let Some(button) = buttons.get(1) {
-                if button.enabled && button.contains(client_x, client_y) {
-                    (button.callback)();
-                    return;
+
+            // The host changes the next game's settings from the panel
+            if in_room && self.handle_settings_click(client_x, client_y) {
+                return;
+            }
+
+            // Check only the visible buttons based on connection state
+            let button_indices = crate::client::ui::visible_buttons(connected, in_room, show_replays);
+            let buttons = self.buttons.lock();
+            for &button_index in button_indices {
+                if let Some(button) = buttons.get(button_index) {
+                    if button.enabled && button.contains(client_x, client_y) {
+                        let callback = button.callback.clone();
+                        drop(buttons);
+                        callback();
+                        return;
+                    }
                 }
             }
-        }
-        drop(buttons);
+            drop(buttons);
         }
 
         // Handle grid clicks
@@ -228,15 +511,16 @@ impl Game {
             *next_id += 1;
 
             // Create a new path with just this cell
+            let path = Path::new(vec![new_cell_id as u32]);
             let mut paths = self.paths.lock();
-            paths.insert(path_id, Mutex::new(Path::new(vec![new_cell_id as u32])));
+            paths.insert(path_id, Mutex::new(path.clone()));
 
             // Set this as the selected path
             *self.selected_path.lock() = Some(path_id);
 
             // Send just this new path to the server
             let mut new_paths = HashMap::new();
-            new_paths.insert(path_id, Path::new(vec![new_cell_id as u32]));
+            new_paths.insert(path_id, path);
             if let Ok(bytes) = bincode::serialize(&SBPacket::UpdatePaths(UpdatePaths { paths: new_paths })) {
                 self.websocket.lock().send_binary(bytes);
             }
@@ -5,6 +5,10 @@ mod websocket;
 mod button;
 mod text_input;
 mod ui;
+mod replay;
+mod chat;
+mod settings;
+mod editor;
 
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
@@ -79,6 +83,10 @@ pub fn start() -> Result<(), JsValue> {
         let rect = click_game.canvas().lock().get_bounding_client_rect();
         let x = event.client_x() as f64 - rect.left();
         let y = event.client_y() as f64 - rect.top();
+        // Shift-click plans a route from the selected cell to the clicked one
+        if event.shift_key() && click_game.route_to_position(x, y) {
+            return;
+        }
         click_game.handle_click(x, y);
     }) as Box<dyn FnMut(web_sys::MouseEvent)>);
 
@@ -89,21 +97,76 @@ pub fn start() -> Result<(), JsValue> {
         )?;
     click_handler.forget();
 
+    // Dragging with the button held paints in the map editor
+    let drag_game = game.clone();
+    let drag_handler = Closure::wrap(Box::new(move |event: web_sys::MouseEvent| {
+        if event.buttons() & 1 == 0 || drag_game.editor.lock().is_none() {
+            return;
+        }
+        let rect = drag_game.canvas().lock().get_bounding_client_rect();
+        drag_game.paint_at(event.client_x() as f64 - rect.left(), event.client_y() as f64 - rect.top());
+    }) as Box<dyn FnMut(web_sys::MouseEvent)>);
+
+    game.canvas().lock()
+        .add_event_listener_with_callback(
+            "mousemove",
+            drag_handler.as_ref().unchecked_ref(),
+        )?;
+    drag_handler.forget();
+
     // Set up keyboard handler
     let keyboard_game = game.clone();
         let keyboard_handler = Closure::wrap(Box::new(move |event: web_sys::KeyboardEvent| {
         let key = event.key().to_lowercase();
+
+        // Replay playback controls take precedence while a replay is open
+        if keyboard_game.replay.lock().is_some() {
+            if keyboard_game.handle_replay_key(key.as_str()) {
+                event.prevent_default();
+            }
+            return;
+        }
+
+        // So do the map editor's brushes and commands
+        if keyboard_game.editor.lock().is_some() {
+            if keyboard_game.handle_editor_key(key.as_str()) {
+                event.prevent_default();
+            }
+            return;
+        }
+
         // Check if any text input is focused
         let has_focused_input = keyboard_game.text_inputs.lock().iter().any(|input| input.focused);
 
         if has_focused_input {
-            keyboard_game.handle_key(key.as_str());
+            // Text inputs need the key as typed
+            keyboard_game.handle_key(&event.key());
         } else {
             match key.as_str() {
+                "enter" if *keyboard_game.connected.lock() => {
+                    keyboard_game.focus_chat();
+                }
                 "w" | "a" | "s" | "d" => {
                     event.prevent_default();
                     keyboard_game.handle_wasd(key.as_str());
                 }
+                "f" => {
+                    keyboard_game.cycle_follow();
+                }
+                // Take back the last step, cancel the selected path or clear every path
+                "backspace" | "z" => {
+                    event.prevent_default();
+                    keyboard_game.undo_step();
+                }
+                "x" => {
+                    keyboard_game.cancel_selected_path();
+                }
+                "q" => {
+                    keyboard_game.cycle_split();
+                }
+                "c" => {
+                    keyboard_game.clear_paths();
+                }
                 _ => {}
             }
         }
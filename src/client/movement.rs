@@ -6,13 +6,30 @@ use parking_lot::Mutex;
 use std::collections::HashMap;
 
 #[cfg(target_arch = "wasm32")]
-use crate::shared::path::Path;
+use crate::shared::path::{Path, SplitMode};
 #[cfg(target_arch = "wasm32")]
-use crate::shared::{SBPacket, sb_packet::UpdatePaths};
+use crate::shared::pathfinding::find_path;
+#[cfg(target_arch = "wasm32")]
+use crate::shared::game_state::GameState;
+#[cfg(target_arch = "wasm32")]
+use crate::shared::{SBPacket, sb_packet::{CancelPath, UndoStep, UpdatePaths}};
 
 #[cfg(target_arch = "wasm32")]
 use super::game::Game;
 
+/// Split modes cycled through with Q
+#[cfg(target_arch = "wasm32")]
+const SPLIT_MODES: [SplitMode; 4] = [SplitMode::AllButOne, SplitMode::Half, SplitMode::Fixed(10), SplitMode::Fixed(25)];
+
+#[cfg(target_arch = "wasm32")]
+pub fn split_label(split: SplitMode) -> String {
+    match split {
+        SplitMode::AllButOne => "all".to_string(),
+        SplitMode::Half => "half".to_string(),
+        SplitMode::Fixed(count) => count.to_string(),
+    }
+}
+
 #[cfg(target_arch = "wasm32")]
 impl Game {
     pub fn get_cell_at_position(&self, x: f64, y: f64) -> Option<usize> {
@@ -96,10 +113,11 @@ impl Game {
         self.selected_cell.lock().replace(new_cell);
 
         // Only extend the selected path with WASD
+        let split = *self.split_mode.lock();
         let paths = self.paths.lock();
         if let Some(path_id) = *self.selected_path.lock() {
             if let Some(path) = paths.get(&path_id) {
-                path.lock().tile_ids.push(new_cell as u32);
+                path.lock().push(new_cell as u32, split);
 
                 // Send just the updated path to server
                 let mut updated_paths = HashMap::new();
@@ -112,4 +130,115 @@ impl Game {
 
         true
     }
-}
\ No newline at end of file
+
+    /// Extends the selected path, or starts a new one at the selected cell, along the
+    /// shortest known route to the cell under the cursor. Swamps are only crossed when
+    /// there is no way around them.
+    pub fn route_to_position(&self, x: f64, y: f64) -> bool {
+        if *self.game_state.lock() != GameState::InGame || self.replay.lock().is_some() {
+            return false;
+        }
+        let Some(target) = self.get_cell_at_position(x, y) else {
+            return false;
+        };
+        let Some(start) = *self.selected_cell.lock() else {
+            return false;
+        };
+
+        let route = {
+            let map_guard = self.map.lock();
+            let Some(map) = map_guard.as_ref() else {
+                return false;
+            };
+            match find_path(map, start, target, true).or_else(|| find_path(map, start, target, false)) {
+                Some(route) if route.len() > 1 => route,
+                _ => return false,
+            }
+        };
+
+        // Keep going on the selected path if it ends where the route begins
+        let mut paths = self.paths.lock();
+        let mut selected_path = self.selected_path.lock();
+        let path_id = match *selected_path {
+            Some(path_id) if paths.get(&path_id).is_some_and(|path| path.lock().tile_ids.last() == Some(&(start as u32))) => {
+                path_id
+            }
+            _ => {
+                let mut next_id = self.next_path_id.lock();
+                let path_id = *next_id;
+                *next_id += 1;
+                paths.insert(path_id, Mutex::new(Path::new(vec![start as u32])));
+                *selected_path = Some(path_id);
+                path_id
+            }
+        };
+
+        let split = *self.split_mode.lock();
+        let mut path = paths[&path_id].lock();
+        for &tile_id in &route[1..] {
+            path.push(tile_id, split);
+        }
+        self.selected_cell.lock().replace(target);
+
+        let updated_paths = HashMap::from([(path_id, path.clone())]);
+        if let Ok(bytes) = bincode::serialize(&SBPacket::UpdatePaths(UpdatePaths { paths: updated_paths })) {
+            self.websocket.lock().send_binary(bytes);
+        }
+        true
+    }
+
+    /// Takes back the last queued tile of the selected path.
+    pub fn undo_step(&self) -> bool {
+        let Some(path_id) = *self.selected_path.lock() else {
+            return false;
+        };
+        let paths = self.paths.lock();
+        let Some(path) = paths.get(&path_id) else {
+            return false;
+        };
+
+        // Tiles the army already reached cannot be taken back
+        let mut path = path.lock();
+        if path.tile_ids.len() <= path.valid_until as usize + 1 {
+            return false;
+        }
+        path.pop();
+        if let Some(&last) = path.tile_ids.last() {
+            self.selected_cell.lock().replace(last as usize);
+        }
+
+        if let Ok(bytes) = bincode::serialize(&SBPacket::UndoStep(UndoStep { path_id })) {
+            self.websocket.lock().send_binary(bytes);
+        }
+        true
+    }
+
+    /// Stops the selected path where its army currently is.
+    pub fn cancel_selected_path(&self) -> bool {
+        let Some(path_id) = self.selected_path.lock().take() else {
+            return false;
+        };
+        self.paths.lock().remove(&path_id);
+
+        if let Ok(bytes) = bincode::serialize(&SBPacket::CancelPath(CancelPath { path_id })) {
+            self.websocket.lock().send_binary(bytes);
+        }
+        true
+    }
+
+    pub fn clear_paths(&self) {
+        self.paths.lock().clear();
+        *self.selected_path.lock() = None;
+
+        if let Ok(bytes) = bincode::serialize(&SBPacket::ClearPaths) {
+            self.websocket.lock().send_binary(bytes);
+        }
+    }
+
+    /// Switches to the next split mode, used by every step added to a path from now on.
+    pub fn cycle_split(&self) {
+        let mut split_mode = self.split_mode.lock();
+        let current = SPLIT_MODES.iter().position(|&mode| mode == *split_mode).unwrap_or(0);
+        *split_mode = SPLIT_MODES[(current + 1) % SPLIT_MODES.len()];
+    }
+}
@@ -2,6 +2,12 @@
 use super::game::Game;
 #[cfg(target_arch = "wasm32")]
 use crate::shared::game_state::GameState;
+#[cfg(target_arch = "wasm32")]
+use crate::shared::path::SplitMode;
+#[cfg(target_arch = "wasm32")]
+use super::movement::split_label;
+#[cfg(target_arch = "wasm32")]
+use super::ui::{host_button_bounds, kick_button_bounds, room_row_bounds, room_watch_bounds, visible_buttons, MAX_ROOM_ROWS, PLAYER_LIST_WIDTH};
 
 #[cfg(target_arch = "wasm32")]
 impl Game {
@@ -42,11 +48,19 @@ impl Game {
     }
 
     fn render_player_list(&self, context: &web_sys::CanvasRenderingContext2d, x: f64, y: f64) {
-        let players = self.players.lock();
+        let all_players = self.players.lock();
+        // Spectators are only counted, never listed
+        let players: Vec<_> = all_players.iter().filter(|p| !p.spectator).collect();
+        let spectator_count = all_players.len() - players.len();
         let padding = 10.0;
         let line_height = 25.0;
-        let box_width = 200.0;
-        let box_height = (players.len() as f64 * line_height) + (padding * 2.0);
+        let box_width = PLAYER_LIST_WIDTH;
+        let host = *self.host.lock();
+        let can_kick = self.is_host() && self.current_room.lock().is_some();
+        let in_lobby = *self.game_state.lock() == GameState::Lobby;
+        let own_id = *self.player_id.lock();
+        let rows = players.len() + if spectator_count > 0 { 1 } else { 0 };
+        let box_height = (rows as f64 * line_height) + (padding * 2.0);
 
         // Draw semi-transparent background
         context.set_fill_style_str("rgba(0, 0, 0, 0.7)");
@@ -65,9 +79,57 @@ impl Game {
                 player.color.r, player.color.g, player.color.b, player.color.a as f64 / 255.0));
             context.fill_rect(x + padding, text_y - 8.0, 16.0, 16.0);
 
-            // Draw player name
-            context.set_fill_style_str("white");
-            let _ = context.fill_text(&player.name, x + padding + 25.0, text_y);
+            // Draw player name, greyed out while they are reconnecting
+            let name = if host == Some(player.id) { format!("{} (host)", player.name) } else { player.name.clone() };
+            if player.bot.is_some() {
+                context.set_fill_style_str("#c0c0c0");
+                let _ = context.fill_text(&format!("{name} (bot)"), x + padding + 25.0, text_y);
+            } else if player.connected {
+                context.set_fill_style_str("white");
+                let _ = context.fill_text(&name, x + padding + 25.0, text_y);
+            } else {
+                context.set_fill_style_str("#808080");
+                let _ = context.fill_text(&format!("{name} (reconnecting)"), x + padding + 25.0, text_y);
+            }
+
+            // Ready mark and team number towards the right end of the row, leaving room for the host and kick buttons
+            context.set_text_align("right");
+            if in_lobby && player.ready {
+                context.set_fill_style_str("#4CAF50");
+                let _ = context.fill_text("✓", x + box_width - padding - 75.0, text_y);
+            }
+            if let Some(team) = player.team {
+                context.set_fill_style_str("#a0a0a0");
+                let _ = context.fill_text(&format!("T{team}"), x + box_width - padding - 42.0, text_y);
+            }
+            context.set_text_align("left");
+
+            // Only humans can take over as host
+            if can_kick && Some(player.id) != own_id && player.bot.is_none() {
+                let (host_x, host_y, host_width, host_height) = host_button_bounds(i);
+                context.set_fill_style_str("#305080");
+                context.fill_rect(host_x, host_y, host_width, host_height);
+                context.set_fill_style_str("white");
+                context.set_text_align("center");
+                let _ = context.fill_text("★", host_x + host_width / 2.0, host_y + host_height / 2.0);
+                context.set_text_align("left");
+            }
+
+            if can_kick && Some(player.id) != own_id {
+                let (kick_x, kick_y, kick_width, kick_height) = kick_button_bounds(i);
+                context.set_fill_style_str("#803030");
+                context.fill_rect(kick_x, kick_y, kick_width, kick_height);
+                context.set_fill_style_str("white");
+                context.set_text_align("center");
+                let _ = context.fill_text("×", kick_x + kick_width / 2.0, kick_y + kick_height / 2.0);
+                context.set_text_align("left");
+            }
+        }
+
+        if spectator_count > 0 {
+            let text_y = y + padding + (players.len() as f64 * line_height) + (line_height / 2.0);
+            context.set_fill_style_str("#a0a0a0");
+            let _ = context.fill_text(&format!("{spectator_count} watching"), x + padding, text_y);
         }
     }
 
@@ -89,193 +151,309 @@ impl Game {
         context.set_text_align("center");
         context.set_text_baseline("middle");
 
-        if !*self.connected.lock() {
-            let _ = context.fill_text("Enter your name to join", logical_width / 2.0, logical_height / 2.0 - 80.0);
+        let connected = *self.connected.lock();
+        let current_room = self.current_room.lock().clone();
 
-            // When not connected, show name input and join button
-            let text_inputs = self.text_inputs.lock();
-            for input in text_inputs.iter() {
-                input.render(context);
+        match (connected, current_room) {
+            (false, _) => {
+                let _ = context.fill_text("Enter your name to join", logical_width / 2.0, logical_height / 2.0 - 80.0);
+
+                // When not connected, show name input
+                if let Some(input) = self.text_inputs.lock().get(0) {
+                    input.render(context);
+                }
             }
-        } else {
-            let _ = context.fill_text("Waiting for game to begin...", logical_width / 2.0, logical_height / 2.0 - 40.0);
+            (true, None) if *self.show_replays.lock() => {
+                let _ = context.fill_text("Choose a replay", logical_width / 2.0, logical_height / 2.0 - 250.0);
+                self.render_replay_list(context, logical_width, logical_height);
+            }
+            (true, None) => {
+                let _ = context.fill_text("Choose a room", logical_width / 2.0, logical_height / 2.0 - 250.0);
+                self.render_room_list(context, logical_width, logical_height);
+
+                // Room name input for creating a new room
+                if let Some(input) = self.text_inputs.lock().get(1) {
+                    input.render(context);
+                }
+            }
+            (true, Some(room)) => {
+                let _ = context.fill_text(&format!("Room {room}"), logical_width / 2.0, logical_height / 2.0 - 80.0);
+                let _ = context.fill_text("Waiting for game to begin...", logical_width / 2.0, logical_height / 2.0 - 40.0);
+
+                // Seed so the map can be shared or reproduced
+                if let Some(seed) = *self.seed.lock() {
+                    context.set_font("14px Arial");
+                    context.set_fill_style_str("#a0a0a0");
+                    let _ = context.fill_text(&format!("Map seed: {seed}"), logical_width / 2.0, logical_height / 2.0 - 10.0);
+                }
 
-            // When connected, show player list
-            self.render_player_list(&context, 20.0, 20.0);
+                // When in a room, show player list and the settings of the next game
+                self.render_player_list(&context, 20.0, 20.0);
+                self.render_settings(context, logical_width);
+            }
         }
 
-        // Render the appropriate button based on connection state
+        // Render the appropriate buttons based on connection state
+        let button_indices = visible_buttons(connected, self.current_room.lock().is_some(), *self.show_replays.lock());
         let buttons = self.buttons.lock();
-        if !*self.connected.lock() {
-            // Show Join button (first button) when not connected
-            if let Some(button) = buttons.get(0) {
-                button.render(context);
-            }
-        } else {
-            // Show Start button (second button) when connected
-            if let Some(button) = buttons.get(1) {
+        for &button_index in button_indices {
+            if let Some(button) = buttons.get(button_index) {
                 button.render(context);
             }
         }
     }
 
+    fn render_replay_list(&self, context: &web_sys::CanvasRenderingContext2d, logical_width: f64, logical_height: f64) {
+        let replays = self.replays.lock();
+
+        context.set_font("16px Arial");
+        context.set_text_baseline("middle");
+
+        if replays.is_empty() {
+            let (x, y, width, height) = room_row_bounds(0, logical_width, logical_height);
+            context.set_fill_style_str("#808080");
+            context.set_text_align("center");
+            let _ = context.fill_text("No replays recorded yet", x + width / 2.0, y + height / 2.0);
+            return;
+        }
+
+        for (i, name) in replays.iter().enumerate().take(MAX_ROOM_ROWS) {
+            let (x, y, width, height) = room_row_bounds(i, logical_width, logical_height);
+
+            context.set_fill_style_str("#303030");
+            context.fill_rect(x, y, width, height);
+            context.set_fill_style_str("white");
+            context.set_text_align("left");
+            let _ = context.fill_text(name, x + 10.0, y + height / 2.0);
+        }
+    }
+
+    fn render_room_list(&self, context: &web_sys::CanvasRenderingContext2d, logical_width: f64, logical_height: f64) {
+        let rooms = self.rooms.lock();
+
+        context.set_font("16px Arial");
+        context.set_text_baseline("middle");
+
+        if rooms.is_empty() {
+            let (x, y, width, height) = room_row_bounds(0, logical_width, logical_height);
+            context.set_fill_style_str("#808080");
+            context.set_text_align("center");
+            let _ = context.fill_text("No rooms yet, create one below", x + width / 2.0, y + height / 2.0);
+            return;
+        }
+
+        for (i, room) in rooms.iter().enumerate().take(MAX_ROOM_ROWS) {
+            let (x, y, width, height) = room_row_bounds(i, logical_width, logical_height);
+
+            context.set_fill_style_str("#303030");
+            context.fill_rect(x, y, width, height);
+
+            let status = match room.game_state {
+                GameState::Lobby => "lobby",
+                GameState::InGame => "in game",
+                GameState::GameOver => "game over",
+            };
+            context.set_fill_style_str("white");
+            context.set_text_align("left");
+            let _ = context.fill_text(&room.name, x + 10.0, y + height / 2.0);
+            context.set_fill_style_str("#a0a0a0");
+            context.set_text_align("right");
+            let _ = context.fill_text(&format!("{} players, {status}", room.player_count), x + width - 70.0, y + height / 2.0);
+
+            // Join as spectator
+            let (watch_x, watch_y, watch_width, watch_height) = room_watch_bounds(i, logical_width, logical_height);
+            context.set_fill_style_str("#404040");
+            context.fill_rect(watch_x, watch_y, watch_width, watch_height);
+            context.set_fill_style_str("white");
+            context.set_text_align("center");
+            let _ = context.fill_text("Watch", watch_x + watch_width / 2.0, watch_y + watch_height / 2.0);
+        }
+    }
+
     pub fn render_grid(&self) {
         let canvas = self.canvas.lock();
         let width = canvas.width() as f64;
         let height = canvas.height() as f64;
         let context = self.context.lock();
 
+        // Replays take over the screen until they are closed
+        if self.replay.lock().is_some() {
+            self.render_map(&context, width, height);
+            self.render_replay_controls(&context, width, height);
+            return;
+        }
+
+        // So does the map editor
+        if self.editor.lock().is_some() {
+            self.render_map(&context, width, height);
+            self.render_editor_hud(&context, width, height);
+            return;
+        }
+
         // Handle different game states
         match *self.game_state.lock() {
             GameState::Lobby => {
                 self.render_lobby(&context, width, height);
             }
             GameState::InGame | GameState::GameOver => {
-                let map_guard = self.map.lock();
-                let Some(map) = map_guard.as_ref() else {
-                    return;
-                };
-
-                // Clear canvas
-                context.set_fill_style_str("#1a1a1a");
-                context.fill_rect(0.0, 0.0, width, height);
-
-                // Grid config
-                let rows = map.height;
-                let cols = map.width;
-
-                let window = web_sys::window().unwrap();
-                let dpr = window.device_pixel_ratio();
-
-                let min_padding = 50.0;  // Use consistent padding value
-                let cell_gap = 1.0;
-                let desired_cell_size = 25.0;
-
-                // Convert to logical pixels for calculations
-                let logical_width = width / dpr;
-                let logical_height = height / dpr;
-                let available_width = logical_width - (2.0 * min_padding);
-                let available_height = logical_height - (2.0 * min_padding);
-
-                // Calculate cell size to maintain aspect ratio
-                let cell_size = {
-                    let by_width = (available_width - (cell_gap * (cols as f64 - 1.0))) / cols as f64;
-                    let by_height = (available_height - (cell_gap * (rows as f64 - 1.0))) / rows as f64;
-                    by_width.min(by_height).min(desired_cell_size)
-                };
-
-                // Calculate actual grid size
-                let grid_width = cols as f64 * (cell_size + cell_gap) - cell_gap;
-                let grid_height = rows as f64 * (cell_size + cell_gap) - cell_gap;
-
-                // Center the grid
-                let x_offset = (logical_width - grid_width) / 2.0;
-                let y_offset = (logical_height - grid_height) / 2.0;
-
-                // Draw cells
-                for row in 0..rows {
-                    for col in 0..cols {
-                        let cell_id = row * cols + col;
-                        let x = x_offset + col as f64 * (cell_size + cell_gap);
-                        let y = y_offset + row as f64 * (cell_size + cell_gap);
-
-                        // Draw cell background
-                        if let Some(cell) = map.cells.get(&cell_id) {
-                            if cell.fog_of_war {
-                                // Fog of war cell - show terrain but with darker background
-                                context.set_fill_style_str("#2a2a2a");  // Dark gray for fog of war
-                            } else if let Some(owner_id) = cell.owner_id {
-                                // Fully visible cell with owner
-                                let players = self.players.lock();
-                                if let Some(owner) = players.iter().find(|p| p.id == owner_id) {
-                                    context.set_fill_style_str(&format!("rgba({}, {}, {}, {})",
-                                        owner.color.r, owner.color.g, owner.color.b, owner.color.a as f64 / 255.0));
-                                } else {
-                                    context.set_fill_style_str("#4a4a4a");  // Default if owner not found
-                                }
-                            } else {
-                                context.set_fill_style_str("#4a4a4a");  // Unowned but visible cell
-                            }
+                self.render_map(&context, width, height);
+            }
+        }
+
+        if *self.connected.lock() {
+            self.render_chat(&context);
+        }
+    }
+
+    fn render_map(&self, context: &web_sys::CanvasRenderingContext2d, width: f64, height: f64) {
+        let map_guard = self.map.lock();
+        let Some(map) = map_guard.as_ref() else {
+            return;
+        };
+
+        // Clear canvas
+        context.set_fill_style_str("#1a1a1a");
+        context.fill_rect(0.0, 0.0, width, height);
+
+        // Grid config
+        let rows = map.height;
+        let cols = map.width;
+
+        let window = web_sys::window().unwrap();
+        let dpr = window.device_pixel_ratio();
+
+        let min_padding = 50.0;  // Use consistent padding value
+        let cell_gap = 1.0;
+        let desired_cell_size = 25.0;
+
+        // Convert to logical pixels for calculations
+        let logical_width = width / dpr;
+        let logical_height = height / dpr;
+        let available_width = logical_width - (2.0 * min_padding);
+        let available_height = logical_height - (2.0 * min_padding);
+
+        // Calculate cell size to maintain aspect ratio
+        let cell_size = {
+            let by_width = (available_width - (cell_gap * (cols as f64 - 1.0))) / cols as f64;
+            let by_height = (available_height - (cell_gap * (rows as f64 - 1.0))) / rows as f64;
+            by_width.min(by_height).min(desired_cell_size)
+        };
+
+        // Calculate actual grid size
+        let grid_width = cols as f64 * (cell_size + cell_gap) - cell_gap;
+        let grid_height = rows as f64 * (cell_size + cell_gap) - cell_gap;
+
+        // Center the grid
+        let x_offset = (logical_width - grid_width) / 2.0;
+        let y_offset = (logical_height - grid_height) / 2.0;
+
+        // Draw cells
+        for row in 0..rows {
+            for col in 0..cols {
+                let cell_id = row * cols + col;
+                let x = x_offset + col as f64 * (cell_size + cell_gap);
+                let y = y_offset + row as f64 * (cell_size + cell_gap);
+
+                // Draw cell background
+                if let Some(cell) = map.cells.get(&cell_id) {
+                    if cell.fog_of_war {
+                        // Fog of war cell - show terrain but with darker background
+                        context.set_fill_style_str("#2a2a2a");  // Dark gray for fog of war
+                    } else if let Some(owner_id) = cell.owner_id {
+                        // Fully visible cell with owner
+                        let players = self.players.lock();
+                        if let Some(owner) = players.iter().find(|p| p.id == owner_id) {
+                            context.set_fill_style_str(&format!("rgba({}, {}, {}, {})",
+                                owner.color.r, owner.color.g, owner.color.b, owner.color.a as f64 / 255.0));
                         } else {
-                            context.set_fill_style_str("#2a2a2a");  // Dark gray for fog of war
-                        }
-                        context.fill_rect(x, y, cell_size, cell_size);
-
-                        // Draw terrain emojis
-                        if let Some(cell) = map.cells.get(&cell_id) {
-                            let emoji = match cell.terrain {
-                                crate::shared::terrain::Terrain::Capital => "👑",
-                                crate::shared::terrain::Terrain::Mountain => "⛰️",
-                                crate::shared::terrain::Terrain::Swamp => "🌿",
-                                crate::shared::terrain::Terrain::City => "🏰",
-                                _ => "",
-                            };
-
-                            if !emoji.is_empty() {
-                                context.set_font(&format!("{}px Arial", cell_size * 0.9));
-                                context.set_text_align("center");
-                                context.set_text_baseline("middle");
-                                if cell.fog_of_war {
-                                    // Draw terrain emoji with reduced opacity for fog of war
-                                    context.set_global_alpha(0.5);
-                                    let _ = context.fill_text(
-                                        emoji,
-                                        x + cell_size / 2.0,
-                                        y + cell_size / 2.0,
-                                    );
-                                    context.set_global_alpha(1.0);
-                                } else {
-                                    let _ = context.fill_text(
-                                        emoji,
-                                        x + cell_size / 2.0,
-                                        y + cell_size / 2.0,
-                                    );
-                                }
-                            }
+                            context.set_fill_style_str("#4a4a4a");  // Default if owner not found
                         }
+                    } else if cell.terrain == crate::shared::terrain::Terrain::Desert {
+                        context.set_fill_style_str("#7a6a3a");  // Sand for unowned desert
+                    } else {
+                        context.set_fill_style_str("#4a4a4a");  // Unowned but visible cell
+                    }
+                } else {
+                    context.set_fill_style_str("#2a2a2a");  // Dark gray for fog of war
+                }
+                context.fill_rect(x, y, cell_size, cell_size);
+
+                // Owned desert keeps a sandy band so it stays recognizable under the owner's color
+                if let Some(cell) = map.cells.get(&cell_id)
+                    && cell.terrain == crate::shared::terrain::Terrain::Desert
+                    && cell.owner_id.is_some()
+                    && !cell.fog_of_war {
+                    context.set_fill_style_str("rgba(210, 180, 110, 0.6)");
+                    context.fill_rect(x, y + cell_size * 0.75, cell_size, cell_size * 0.25);
+                }
 
-                        // Draw troop count if cell is visible and not in fog of war
-                        if let Some(cell) = map.cells.get(&cell_id) {
-                            if cell.troops > 0 && !cell.fog_of_war {
-                                context.set_fill_style_str("white");
-                                context.set_text_align("center");
-                                context.set_text_baseline("middle");
-                                context.set_font(&format!("{}px Arial", cell_size * 0.5));
-                                let _ = context.fill_text(
-                                    &cell.troops.to_string(),
-                                    x + cell_size / 2.0,
-                                    y + cell_size / 2.0,
-                                );
-                            }
+                // Draw terrain emojis
+                if let Some(cell) = map.cells.get(&cell_id) {
+                    let emoji = match cell.terrain {
+                        crate::shared::terrain::Terrain::Capital => "👑",
+                        crate::shared::terrain::Terrain::Mountain => "⛰️",
+                        crate::shared::terrain::Terrain::Swamp => "🌿",
+                        crate::shared::terrain::Terrain::City => "🏰",
+                        _ => "",
+                    };
+
+                    if !emoji.is_empty() {
+                        context.set_font(&format!("{}px Arial", cell_size * 0.9));
+                        context.set_text_align("center");
+                        context.set_text_baseline("middle");
+                        if cell.fog_of_war {
+                            // Draw terrain emoji with reduced opacity for fog of war
+                            context.set_global_alpha(0.5);
+                            let _ = context.fill_text(
+                                emoji,
+                                x + cell_size / 2.0,
+                                y + cell_size / 2.0,
+                            );
+                            context.set_global_alpha(1.0);
+                        } else {
+                            let _ = context.fill_text(
+                                emoji,
+                                x + cell_size / 2.0,
+                                y + cell_size / 2.0,
+                            );
                         }
+                    }
+                }
 
-                        // Draw path if this cell is part of one
-                        let paths = self.paths.lock();
-                        for path in paths.values() {
-                            let path_guard = path.lock();
-                            let tile_index = path_guard.tile_ids.iter().position(|&id| id == cell_id as u32);
-                            if let Some(index) = tile_index {
-                                if index as u32 > path_guard.valid_until {
-                                    // Use the first tile in the path for the color
-                                    if let Some(&first_tile) = path_guard.tile_ids.first() {
-                                        context.set_stroke_style_str(&self.get_path_color(first_tile));
-                                        context.set_line_width(2.0);
-                                        let padding = 1.0;
-                                        context.stroke_rect(
-                                            x - padding/2.0,
-                                            y - padding/2.0,
-                                            cell_size + padding,
-                                            cell_size + padding
-                                        );
-                                    }
-                                }
-                            }
-                        }
+                // Draw troop count if cell is visible and not in fog of war
+                if let Some(cell) = map.cells.get(&cell_id) {
+                    if cell.troops > 0 && !cell.fog_of_war {
+                        context.set_fill_style_str("white");
+                        context.set_text_align("center");
+                        context.set_text_baseline("middle");
+                        context.set_font(&format!("{}px Arial", cell_size * 0.5));
+                        let _ = context.fill_text(
+                            &cell.troops.to_string(),
+                            x + cell_size / 2.0,
+                            y + cell_size / 2.0,
+                        );
+                    }
+                }
 
-                        // Draw selection border if this is the selected cell
-                        if let Some(selected) = *self.selected_cell.lock() {
-                            if selected == cell_id {
-                                context.set_stroke_style_str("#ffd700"); // Gold color
+                // Draw path if this cell is part of one
+                let paths = self.paths.lock();
+                for path in paths.values() {
+                    let path_guard = path.lock();
+                    let tile_index = path_guard.tile_ids.iter().position(|&id| id == cell_id as u32);
+                    if let Some(index) = tile_index {
+                        // Mark the tiles still to be left with only part of the army
+                        let split = path_guard.split_at(index as u32);
+                        if index as u32 >= path_guard.valid_until && index + 1 < path_guard.tile_ids.len() && split != SplitMode::AllButOne {
+                            context.set_fill_style_str("#ffd700");
+                            context.set_font(&format!("{}px Arial", cell_size * 0.35));
+                            context.set_text_align("right");
+                            context.set_text_baseline("top");
+                            let _ = context.fill_text(&split_label(split), x + cell_size - 1.0, y + 1.0);
+                        }
+                        if index as u32 > path_guard.valid_until {
+                            // Use the first tile in the path for the color
+                            if let Some(&first_tile) = path_guard.tile_ids.first() {
+                                context.set_stroke_style_str(&self.get_path_color(first_tile));
                                 context.set_line_width(2.0);
                                 let padding = 1.0;
                                 context.stroke_rect(
@@ -289,9 +467,59 @@ impl Game {
                     }
                 }
 
-                // Draw player list overlay
-                self.render_player_list(&context, 20.0, 20.0);
+                // Draw selection border if this is the selected cell
+                if let Some(selected) = *self.selected_cell.lock() {
+                    if selected == cell_id {
+                        context.set_stroke_style_str("#ffd700"); // Gold color
+                        context.set_line_width(2.0);
+                        let padding = 1.0;
+                        context.stroke_rect(
+                            x - padding/2.0,
+                            y - padding/2.0,
+                            cell_size + padding,
+                            cell_size + padding
+                        );
+                    }
+                }
             }
         }
+
+        // Maps in the editor have no players yet
+        if self.editor.lock().is_some() {
+            return;
+        }
+
+        // Draw player list overlay
+        self.render_player_list(context, 20.0, 20.0);
+
+        // Show how much of an army new moves take along
+        if !self.is_spectator() && self.replay.lock().is_none() {
+            context.set_font("16px Arial");
+            context.set_fill_style_str("white");
+            context.set_text_align("center");
+            context.set_text_baseline("middle");
+            let _ = context.fill_text(
+                &format!("Move: {} (Q to change)", split_label(*self.split_mode.lock())),
+                logical_width / 2.0,
+                logical_height - min_padding / 2.0,
+            );
+        }
+
+        // Tell spectators whose perspective they are watching
+        if self.is_spectator() {
+            let following = *self.following.lock();
+            let watching = following
+                .and_then(|id| self.players.lock().iter().find(|p| p.id == id).map(|p| p.name.clone()))
+                .unwrap_or_else(|| "full map".to_string());
+            context.set_font("16px Arial");
+            context.set_fill_style_str("white");
+            context.set_text_align("center");
+            context.set_text_baseline("middle");
+            let _ = context.fill_text(
+                &format!("Spectating: {watching} (F to switch)"),
+                logical_width / 2.0,
+                min_padding / 2.0,
+            );
+        }
     }
 }
\ No newline at end of file
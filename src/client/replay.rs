@@ -0,0 +1,171 @@
+#[cfg(target_arch = "wasm32")]
+use uuid::Uuid;
+
+#[cfg(target_arch = "wasm32")]
+use super::game::Game;
+#[cfg(target_arch = "wasm32")]
+use crate::shared::{sb_packet::ReplayControl, SBPacket};
+
+/// Playback speeds cycled through with + and -
+#[cfg(target_arch = "wasm32")]
+const SPEEDS: [f32; 7] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0];
+
+/// Ticks skipped by the arrow keys
+#[cfg(target_arch = "wasm32")]
+const SEEK_STEP: u32 = 10;
+
+#[cfg(target_arch = "wasm32")]
+pub struct ReplayState {
+    pub name: String,
+    pub seed: u64,
+    pub total_ticks: u32,
+    pub tick: u32,
+    pub playing: bool,
+    pub speed: f32,
+    pub perspective: Option<Uuid>,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl ReplayState {
+    pub fn new(name: String, seed: u64, total_ticks: u32) -> Self {
+        Self {
+            name,
+            seed,
+            total_ticks,
+            tick: 0,
+            playing: true,
+            speed: 1.0,
+            perspective: None,
+        }
+    }
+}
+
+/// Bounds of the replay progress bar as (x, y, width, height)
+#[cfg(target_arch = "wasm32")]
+fn progress_bar_bounds(logical_width: f64, logical_height: f64) -> (f64, f64, f64, f64) {
+    let margin = 50.0;
+    (margin, logical_height - 35.0, logical_width - 2.0 * margin, 10.0)
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Game {
+    fn send_replay_control(&self, control: ReplayControl) {
+        if let Ok(bytes) = bincode::serialize(&SBPacket::ReplayControl(control)) {
+            self.websocket.lock().send_binary(bytes);
+        }
+    }
+
+    /// Handles a key press while a replay is open. Returns true if the key was used.
+    pub fn handle_replay_key(&self, key: &str) -> bool {
+        let mut replay_guard = self.replay.lock();
+        let Some(replay) = replay_guard.as_mut() else {
+            return false;
+        };
+
+        let control = match key {
+            " " => if replay.playing { ReplayControl::Pause } else { ReplayControl::Play },
+            "arrowleft" => ReplayControl::Seek(replay.tick.saturating_sub(SEEK_STEP)),
+            "arrowright" => ReplayControl::Seek((replay.tick + SEEK_STEP).min(replay.total_ticks)),
+            "+" | "=" | "-" => {
+                let current = SPEEDS.iter().position(|&speed| speed >= replay.speed).unwrap_or(2);
+                let next = if key == "-" {
+                    current.saturating_sub(1)
+                } else {
+                    (current + 1).min(SPEEDS.len() - 1)
+                };
+                ReplayControl::SetSpeed(SPEEDS[next])
+            }
+            "f" => {
+                // Cycle through each player's perspective, then back to the full map
+                let players = self.players.lock();
+                let next = match replay.perspective {
+                    None => players.first().map(|p| p.id),
+                    Some(current) => players.iter()
+                        .position(|p| p.id == current)
+                        .and_then(|i| players.get(i + 1))
+                        .map(|p| p.id),
+                };
+                replay.perspective = next;
+                ReplayControl::Perspective(next)
+            }
+            "escape" => {
+                replay_guard.take();
+                self.map.lock().take();
+                self.players.lock().clear();
+                ReplayControl::Stop
+            }
+            _ => return false,
+        };
+        drop(replay_guard);
+
+        self.send_replay_control(control);
+        true
+    }
+
+    /// Seeks when the progress bar is clicked. Returns true if the click was used.
+    pub fn handle_replay_click(&self, x: f64, y: f64) -> bool {
+        let total_ticks = match self.replay.lock().as_ref() {
+            Some(replay) => replay.total_ticks,
+            None => return false,
+        };
+
+        let (logical_width, logical_height) = self.logical_size();
+        let (bar_x, bar_y, bar_width, bar_height) = progress_bar_bounds(logical_width, logical_height);
+
+        // Be generous with the vertical hit area, the bar is thin
+        if x >= bar_x && x <= bar_x + bar_width && y >= bar_y - 10.0 && y <= bar_y + bar_height + 10.0 {
+            let fraction = (x - bar_x) / bar_width;
+            self.send_replay_control(ReplayControl::Seek((fraction * total_ticks as f64).round() as u32));
+        }
+        true
+    }
+
+    pub fn render_replay_controls(&self, context: &web_sys::CanvasRenderingContext2d, width: f64, height: f64) {
+        let replay_guard = self.replay.lock();
+        let Some(replay) = replay_guard.as_ref() else {
+            return;
+        };
+
+        let dpr = web_sys::window().unwrap().device_pixel_ratio();
+        let logical_width = width / dpr;
+        let logical_height = height / dpr;
+
+        // Progress bar
+        let (bar_x, bar_y, bar_width, bar_height) = progress_bar_bounds(logical_width, logical_height);
+        let progress = if replay.total_ticks == 0 { 0.0 } else { replay.tick as f64 / replay.total_ticks as f64 };
+        context.set_fill_style_str("#404040");
+        context.fill_rect(bar_x, bar_y, bar_width, bar_height);
+        context.set_fill_style_str("#4CAF50");
+        context.fill_rect(bar_x, bar_y, bar_width * progress, bar_height);
+
+        // Status line
+        let perspective = replay.perspective
+            .and_then(|id| self.players.lock().iter().find(|p| p.id == id).map(|p| p.name.clone()))
+            .unwrap_or_else(|| "full map".to_string());
+        context.set_font("14px Arial");
+        context.set_fill_style_str("white");
+        context.set_text_align("left");
+        context.set_text_baseline("middle");
+        let _ = context.fill_text(
+            &format!(
+                "{} {} (seed {})  tick {}/{}  {}x  view: {}",
+                if replay.playing { "Playing" } else { "Paused" },
+                replay.name,
+                replay.seed,
+                replay.tick,
+                replay.total_ticks,
+                replay.speed,
+                perspective,
+            ),
+            bar_x,
+            bar_y - 15.0,
+        );
+        context.set_text_align("right");
+        context.set_fill_style_str("#a0a0a0");
+        let _ = context.fill_text(
+            "Space play/pause, arrows seek, +/- speed, F view, Esc exit",
+            bar_x + bar_width,
+            bar_y - 15.0,
+        );
+    }
+}
@@ -0,0 +1,152 @@
+#[cfg(target_arch = "wasm32")]
+use super::game::Game;
+#[cfg(target_arch = "wasm32")]
+use crate::shared::{settings::GameSettings, SBPacket};
+
+/// Rows in the lobby settings panel
+#[cfg(target_arch = "wasm32")]
+const SETTING_ROWS: usize = 11;
+
+/// Bounds of a row in the settings panel as (x, y, width, height), the panel is drawn at the top right
+#[cfg(target_arch = "wasm32")]
+fn setting_row_bounds(index: usize, logical_width: f64) -> (f64, f64, f64, f64) {
+    let row_width = 280.0;
+    let row_height = 26.0;
+    (
+        logical_width - row_width - 20.0,
+        50.0 + index as f64 * (row_height + 4.0),
+        row_width,
+        row_height,
+    )
+}
+
+/// Bounds of the "-" or "+" control at the right end of a settings row
+#[cfg(target_arch = "wasm32")]
+fn setting_control_bounds(index: usize, logical_width: f64, increase: bool) -> (f64, f64, f64, f64) {
+    let (x, y, width, height) = setting_row_bounds(index, logical_width);
+    let size = height - 4.0;
+    let control_x = if increase { x + width - size - 2.0 } else { x + width - 2.0 * size - 6.0 };
+    (control_x, y + 2.0, size, size)
+}
+
+/// Name and current value of a row in the settings panel
+#[cfg(target_arch = "wasm32")]
+fn setting_text(settings: &GameSettings, index: usize) -> (&'static str, String) {
+    let on_off = |value: bool| if value { "on" } else { "off" }.to_string();
+    let percent = |value: f32| format!("{:.0}%", value * 100.0);
+    match index {
+        0 => ("Map width", settings.map_width.to_string()),
+        1 => ("Map height", settings.map_height.to_string()),
+        2 => ("Mountains", percent(settings.mountain_density)),
+        3 => ("Deserts", percent(settings.desert_density)),
+        4 => ("Swamps", percent(settings.swamp_density)),
+        5 => ("Cities", percent(settings.city_density)),
+        6 => ("Tick", format!("{:.0}ms", settings.tick_ms)),
+        7 => ("Fog on mountains", on_off(settings.fow_mountains)),
+        8 => ("Fog on swamps", on_off(settings.fow_swamps)),
+        9 => ("Disguise cities", on_off(settings.disguise_cities_as_mountains)),
+        _ => ("Map", settings.map.clone().unwrap_or_else(|| "generated".to_string())),
+    }
+}
+
+/// Moves one setting a step up or down, switches get toggled either way and maps are cycled through
+#[cfg(target_arch = "wasm32")]
+fn adjust_setting(settings: &mut GameSettings, index: usize, increase: bool, maps: &[String]) {
+    let size_step = |value: usize| if increase { value + 5 } else { value.saturating_sub(5) };
+    let density_step = |value: f32| if increase { value + 0.02 } else { value - 0.02 };
+    match index {
+        0 => settings.map_width = size_step(settings.map_width),
+        1 => settings.map_height = size_step(settings.map_height),
+        2 => settings.mountain_density = density_step(settings.mountain_density),
+        3 => settings.desert_density = density_step(settings.desert_density),
+        4 => settings.swamp_density = density_step(settings.swamp_density),
+        5 => settings.city_density = density_step(settings.city_density),
+        6 => settings.tick_ms += if increase { 50.0 } else { -50.0 },
+        7 => settings.fow_mountains = !settings.fow_mountains,
+        8 => settings.fow_swamps = !settings.fow_swamps,
+        9 => settings.disguise_cities_as_mountains = !settings.disguise_cities_as_mountains,
+        _ => {
+            // Generated maps come before the hand-made ones
+            let options: Vec<Option<&String>> = std::iter::once(None).chain(maps.iter().map(Some)).collect();
+            let current = options.iter().position(|option| *option == settings.map.as_ref()).unwrap_or(0);
+            let next = (if increase { current + 1 } else { current + options.len() - 1 }) % options.len();
+            settings.map = options[next].cloned();
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Game {
+    /// Handles a click on the settings panel. Only the host can change anything.
+    /// Returns true if the click hit one of the controls.
+    pub fn handle_settings_click(&self, client_x: f64, client_y: f64) -> bool {
+        if !self.is_host() {
+            return false;
+        }
+        let Some(room_settings) = self.room_settings.lock().clone() else {
+            return false;
+        };
+
+        let (logical_width, _) = self.logical_size();
+        for index in 0..SETTING_ROWS {
+            for increase in [false, true] {
+                let (x, y, width, height) = setting_control_bounds(index, logical_width, increase);
+                if client_x >= x && client_x <= x + width && client_y >= y && client_y <= y + height {
+                    let mut settings = room_settings.settings.clone();
+                    adjust_setting(&mut settings, index, increase, &room_settings.maps);
+                    let settings = settings.clamp(&room_settings.bounds);
+                    if settings != room_settings.settings
+                        && let Ok(bytes) = bincode::serialize(&SBPacket::SetSettings(settings)) {
+                        self.websocket.lock().send_binary(bytes);
+                    }
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Shows the settings of the room's next game, with controls for the host.
+    pub fn render_settings(&self, context: &web_sys::CanvasRenderingContext2d, logical_width: f64) {
+        let Some(room_settings) = self.room_settings.lock().clone() else {
+            return;
+        };
+        let host = self.is_host();
+
+        let (x, y, ..) = setting_row_bounds(0, logical_width);
+        context.set_font("16px Arial");
+        context.set_text_baseline("middle");
+        context.set_text_align("left");
+        context.set_fill_style_str("white");
+        let _ = context.fill_text(if host { "Game settings" } else { "Game settings (host only)" }, x, y - 15.0);
+
+        for index in 0..SETTING_ROWS {
+            let (x, y, width, height) = setting_row_bounds(index, logical_width);
+            let (name, value) = setting_text(&room_settings.settings, index);
+
+            context.set_fill_style_str("#303030");
+            context.fill_rect(x, y, width, height);
+            context.set_font("14px Arial");
+            context.set_text_align("left");
+            context.set_fill_style_str("white");
+            let _ = context.fill_text(name, x + 8.0, y + height / 2.0);
+
+            // Value left of the controls
+            let (minus_x, ..) = setting_control_bounds(index, logical_width, false);
+            context.set_text_align("right");
+            context.set_fill_style_str("#a0a0a0");
+            let _ = context.fill_text(&value, minus_x - 8.0, y + height / 2.0);
+
+            if host {
+                for increase in [false, true] {
+                    let (control_x, control_y, size, _) = setting_control_bounds(index, logical_width, increase);
+                    context.set_fill_style_str("#404040");
+                    context.fill_rect(control_x, control_y, size, size);
+                    context.set_fill_style_str("white");
+                    context.set_text_align("center");
+                    let _ = context.fill_text(if increase { "+" } else { "-" }, control_x + size / 2.0, control_y + size / 2.0);
+                }
+            }
+        }
+    }
+}
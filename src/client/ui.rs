@@ -5,11 +5,73 @@ use {
             button::Button, game::Game, text_input::TextInput, websocket::WebSocketClient
         },
         shared::{
-            sb_packet::Login, Color, SBPacket
+            player::{BotDifficulty, MAX_TEAMS}, sb_packet::{AddBot, Login, CreateRoom, SetReady, SetSpectator, SetTeam}, Color, SBPacket
         },
     }, parking_lot::Mutex, rand::Rng, std::rc::Rc
 };
 
+/// Number of rooms shown in the room browser
+#[cfg(target_arch = "wasm32")]
+pub const MAX_ROOM_ROWS: usize = 6;
+
+/// Bounds of a row in the room browser as (x, y, width, height)
+#[cfg(target_arch = "wasm32")]
+pub fn room_row_bounds(index: usize, logical_width: f64, logical_height: f64) -> (f64, f64, f64, f64) {
+    let row_width = 300.0;
+    let row_height = 30.0;
+    (
+        (logical_width - row_width) / 2.0,
+        logical_height / 2.0 - 220.0 + index as f64 * (row_height + 5.0),
+        row_width,
+        row_height,
+    )
+}
+
+/// Bounds of the "watch" area at the right end of a room browser row
+#[cfg(target_arch = "wasm32")]
+pub fn room_watch_bounds(index: usize, logical_width: f64, logical_height: f64) -> (f64, f64, f64, f64) {
+    let (x, y, width, height) = room_row_bounds(index, logical_width, logical_height);
+    let watch_width = 60.0;
+    (x + width - watch_width, y, watch_width, height)
+}
+
+/// Bounds of the kick button at the end of row `index` in the player list, which is drawn at the top left
+#[cfg(target_arch = "wasm32")]
+pub fn kick_button_bounds(index: usize) -> (f64, f64, f64, f64) {
+    let (x, y) = (20.0, 20.0);
+    let padding = 10.0;
+    let line_height = 25.0;
+    let size = 16.0;
+    (
+        x + PLAYER_LIST_WIDTH - padding - size,
+        y + padding + index as f64 * line_height + (line_height - size) / 2.0,
+        size,
+        size,
+    )
+}
+
+/// Bounds of the button handing the host role to the player in row `index`, left of the kick button
+#[cfg(target_arch = "wasm32")]
+pub fn host_button_bounds(index: usize) -> (f64, f64, f64, f64) {
+    let (x, y, width, height) = kick_button_bounds(index);
+    (x - width - 4.0, y, width, height)
+}
+
+/// Width of the player list
+#[cfg(target_arch = "wasm32")]
+pub const PLAYER_LIST_WIDTH: f64 = 260.0;
+
+/// Indices of the buttons shown on the current lobby screen
+#[cfg(target_arch = "wasm32")]
+pub fn visible_buttons(connected: bool, in_room: bool, show_replays: bool) -> &'static [usize] {
+    match (connected, in_room) {
+        (false, _) => &[0],                        // Join button
+        (true, false) if show_replays => &[4],     // Back to rooms
+        (true, false) => &[2, 4, 10],              // Create Room, Replays and Map Editor buttons
+        (true, true) => &[1, 3, 5, 6, 7, 8, 9, 11], // Start, Spectate, bot, team, ready and Leave buttons
+    }
+}
+
 #[cfg(target_arch = "wasm32")]
 pub fn get_buttons(game: Rc<Game>, logical_width: f64, logical_height: f64) -> Vec<Button> {
     let button_width = 200.0;
@@ -61,9 +123,173 @@ pub fn get_buttons(game: Rc<Game>, logical_width: f64, logical_height: f64) -> V
                 })
             },
         ),
+        // Create Room button (shown while choosing a room)
+        Button::new(
+            "Create Room".to_string(),
+            (logical_width - button_width) / 2.0,  // center horizontally
+            logical_height / 2.0 + 80.0,    // below the room name input
+            button_width,
+            button_height,
+            {
+                let game = game.clone();
+                Rc::new(move || {
+                    if let Ok(bytes) = bincode::serialize(&SBPacket::CreateRoom(CreateRoom {
+                        name: game.room_name.lock().trim().to_string(),
+                    })) {
+                        game.websocket.lock().send_binary(bytes);
+                    }
+                })
+            },
+        ),
+        // Spectate/Play toggle (shown in a room's lobby)
+        Button::new(
+            "Spectate".to_string(),
+            (logical_width - button_width) / 2.0,  // center horizontally
+            logical_height / 2.0 + 80.0,    // below the start button
+            button_width,
+            button_height,
+            {
+                let game = game.clone();
+                Rc::new(move || {
+                    let spectator = !game.is_spectator();
+                    if let Ok(bytes) = bincode::serialize(&SBPacket::SetSpectator(SetSpectator { spectator })) {
+                        game.websocket.lock().send_binary(bytes);
+                    }
+                })
+            },
+        ),
+        // Replays/Rooms toggle (shown while choosing a room)
+        Button::new(
+            "Replays".to_string(),
+            (logical_width - button_width) / 2.0,  // center horizontally
+            logical_height / 2.0 + 140.0,   // below the create button
+            button_width,
+            button_height,
+            {
+                let game = game.clone();
+                Rc::new(move || {
+                    let show_replays = {
+                        let mut show_replays = game.show_replays.lock();
+                        *show_replays = !*show_replays;
+                        *show_replays
+                    };
+                    if let Some(button) = game.buttons.lock().get_mut(4) {
+                        button.text = if show_replays { "Rooms" } else { "Replays" }.to_string();
+                    }
+                    let packet = if show_replays { SBPacket::ListReplays } else { SBPacket::ListRooms };
+                    if let Ok(bytes) = bincode::serialize(&packet) {
+                        game.websocket.lock().send_binary(bytes);
+                    }
+                })
+            },
+        ),
+        // Add Bot buttons (shown in a room's lobby)
+        bot_button(game.clone(), "Add Expansion Bot", BotDifficulty::Expansion, logical_width, logical_height / 2.0 + 140.0),
+        bot_button(game.clone(), "Add Aggressive Bot", BotDifficulty::Aggressive, logical_width, logical_height / 2.0 + 200.0),
+        // Remove Bots button (shown in a room's lobby)
+        Button::new(
+            "Remove Bots".to_string(),
+            (logical_width - button_width) / 2.0,  // center horizontally
+            logical_height / 2.0 + 260.0,   // below the bot buttons
+            button_width,
+            button_height,
+            {
+                let game = game.clone();
+                Rc::new(move || {
+                    if let Ok(bytes) = bincode::serialize(&SBPacket::RemoveBots) {
+                        game.websocket.lock().send_binary(bytes);
+                    }
+                })
+            },
+        ),
+        // Team picker, cycles through the teams and back to playing alone (shown in a room's lobby)
+        Button::new(
+            "Team: none".to_string(),
+            (logical_width - button_width) / 2.0,  // center horizontally
+            logical_height / 2.0 + 320.0,   // below the remove bots button
+            button_width,
+            button_height,
+            {
+                let game = game.clone();
+                Rc::new(move || {
+                    let team = match game.own_team() {
+                        None => Some(1),
+                        Some(team) if team < MAX_TEAMS => Some(team + 1),
+                        Some(_) => None,
+                    };
+                    if let Ok(bytes) = bincode::serialize(&SBPacket::SetTeam(SetTeam { team })) {
+                        game.websocket.lock().send_binary(bytes);
+                    }
+                })
+            },
+        ),
+        // Ready toggle, the host can start once enough players are ready (shown in a room's lobby)
+        Button::new(
+            "Ready".to_string(),
+            (logical_width - button_width) / 2.0,  // center horizontally
+            logical_height / 2.0 + 380.0,   // below the team picker
+            button_width,
+            button_height,
+            {
+                let game = game.clone();
+                Rc::new(move || {
+                    let ready = !game.is_ready();
+                    if let Ok(bytes) = bincode::serialize(&SBPacket::SetReady(SetReady { ready })) {
+                        game.websocket.lock().send_binary(bytes);
+                    }
+                })
+            },
+        ),
+        // Map Editor button (shown while choosing a room)
+        Button::new(
+            "Map Editor".to_string(),
+            (logical_width - button_width) / 2.0,  // center horizontally
+            logical_height / 2.0 + 200.0,   // below the replays button
+            button_width,
+            button_height,
+            {
+                let game = game.clone();
+                Rc::new(move || {
+                    game.open_editor();
+                })
+            },
+        ),
+        // Leave Room button (shown in a room's lobby)
+        Button::new(
+            "Leave Room".to_string(),
+            logical_width - button_width - 20.0,  // bottom right, below the settings panel
+            logical_height - button_height - 20.0,
+            button_width,
+            button_height,
+            {
+                let game = game.clone();
+                Rc::new(move || {
+                    game.leave_room();
+                })
+            },
+        ),
     ]
 }
 
+#[cfg(target_arch = "wasm32")]
+fn bot_button(game: Rc<Game>, text: &str, difficulty: BotDifficulty, logical_width: f64, y: f64) -> Button {
+    let button_width = 200.0;
+    let button_height = 50.0;
+
+    Button::new(
+        text.to_string(),
+        (logical_width - button_width) / 2.0,  // center horizontally
+        y,
+        button_width,
+        button_height,
+        Rc::new(move || {
+            if let Ok(bytes) = bincode::serialize(&SBPacket::AddBot(AddBot { difficulty })) {
+                game.websocket.lock().send_binary(bytes);
+            }
+        }),
+    )
+}
+
 #[cfg(target_arch = "wasm32")]
 pub fn get_text_inputs(game: Rc<Game>, logical_width: f64, logical_height: f64) -> Vec<TextInput> {
     let button_width = 200.0;  // Use same width as buttons for consistency
@@ -83,5 +309,27 @@ pub fn get_text_inputs(game: Rc<Game>, logical_width: f64, logical_height: f64)
                 })
             },
         ),
+        TextInput::new(
+            "New room name...".to_string(),
+            (logical_width - button_width) / 2.0,  // center horizontally
+            logical_height / 2.0 + 20.0,    // below the room list
+            button_width,
+            40.0,
+            {
+                let game = game.clone();
+                Rc::new(move |text| {
+                    *game.room_name.lock() = text.to_string();
+                })
+            },
+        ),
+        // Chat input (shown on every screen once connected)
+        TextInput::new(
+            "Chat (/t for team)...".to_string(),
+            20.0,
+            logical_height - 50.0,          // bottom left corner
+            300.0,
+            30.0,
+            Rc::new(|_| {}),
+        ),
     ]
 }
@@ -18,11 +18,28 @@ use crate::client::game::Game;
 use crate::shared::{CBPacket, SBPacket, Color};
 #[cfg(target_arch = "wasm32")]
 use crate::shared::sb_packet::Login;
+#[cfg(target_arch = "wasm32")]
+use crate::shared::game_state::GameState;
+#[cfg(target_arch = "wasm32")]
+use crate::client::replay::ReplayState;
+
+#[cfg(target_arch = "wasm32")]
+use uuid::Uuid;
+#[cfg(target_arch = "wasm32")]
+use crate::shared::sb_packet::Resume;
+
+/// Milliseconds to wait between attempts to reopen a dropped connection
+#[cfg(target_arch = "wasm32")]
+const RECONNECT_INTERVAL_MS: f64 = 2000.0;
 
 #[cfg(target_arch = "wasm32")]
 pub struct WebSocketClient {
     client: Rc<RefCell<PollingClient>>,
     login_sent: bool,
+    server_url: String,
+    session_token: Option<Uuid>,
+    resume_pending: bool,
+    last_reconnect_attempt: f64,
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -42,10 +59,39 @@ impl WebSocketClient {
         Ok(WebSocketClient {
             client: client.clone(),
             login_sent: false,
+            server_url,
+            session_token: None,
+            resume_pending: false,
+            last_reconnect_attempt: 0.0,
         })
     }
 
     pub fn update(&mut self, game: &Rc<Game>) -> Result<(), JsValue> {
+        // Reopen dropped connections and resume the session once logged in
+        let status = self.client.borrow().status();
+        match status {
+            ConnectionStatus::Error | ConnectionStatus::Disconnected if self.session_token.is_some() => {
+                let now = js_sys::Date::now();
+                if now - self.last_reconnect_attempt >= RECONNECT_INTERVAL_MS {
+                    self.last_reconnect_attempt = now;
+                    info!("Connection lost, reconnecting...");
+                    if let Ok(client) = PollingClient::new(&self.server_url) {
+                        self.client = Rc::new(RefCell::new(client));
+                        self.resume_pending = true;
+                    }
+                }
+            }
+            ConnectionStatus::Connected if self.resume_pending => {
+                if let Some(session_token) = self.session_token {
+                    info!("Reconnected, resuming session");
+                    if let Ok(bytes) = bincode::serialize(&SBPacket::Resume(Resume { session_token })) {
+                        self.send_binary(bytes);
+                    }
+                }
+                self.resume_pending = false;
+            }
+            _ => {}
+        }
 
         // Handle any new messages
         let messages = self.client.borrow_mut().receive();
@@ -69,33 +115,136 @@ impl WebSocketClient {
         self.client.borrow_mut().send_binary(bytes).ok();
     }
 
-    fn handle_packet(&self, pkt: CBPacket, game: &Rc<Game>) {
+    fn handle_packet(&mut self, pkt: CBPacket, game: &Rc<Game>) {
         match pkt {
-            CBPacket::LoginAccepted(_) => {
+            CBPacket::LoginAccepted(login_accepted) => {
                 info!("Login accepted");
-                if let Ok(bytes) = bincode::serialize(&SBPacket::GiveMeMap) {
-                    self.client.borrow_mut().send_binary(bytes)
-                        .map_err(|e| JsValue::from_str(&format!("Failed to send GiveMeMap: {:?}", e))).ok();
-                }
+                self.session_token = Some(login_accepted.session_token);
+                *game.player_id.lock() = Some(login_accepted.player_id);
+                *game.settings_bounds.lock() = login_accepted.settings_bounds;
                 *game.connected.lock() = true;
             }
+            CBPacket::ResumeRejected => {
+                info!("Session expired, back to login");
+                self.session_token = None;
+                *game.connected.lock() = false;
+                *game.current_room.lock() = None;
+                *game.game_state.lock() = GameState::Lobby;
+                game.map.lock().take();
+                game.paths.lock().clear();
+            }
             CBPacket::MapSync(map_sync) => {
                 info!("Processing map sync packet");
-                game.map.lock().replace(map_sync.map);
+                game.replace_map(map_sync.map);
+                *game.map_tick.lock() = Some(map_sync.tick);
+            }
+            CBPacket::MapDelta(delta) => {
+                let mut map_tick = game.map_tick.lock();
+                let Some(tick) = *map_tick else {
+                    // Still waiting for a snapshot
+                    return;
+                };
+
+                let mut map = game.map.lock();
+                match map.as_mut() {
+                    Some(map) if delta.tick == tick + 1 => {
+                        *map_tick = Some(delta.tick);
+                        map.apply_delta(delta);
+                    }
+                    _ => {
+                        // An update went missing, start over from a fresh snapshot
+                        info!("Map update {} does not follow {tick}, resyncing", delta.tick);
+                        *map_tick = None;
+                        if let Ok(bytes) = bincode::serialize(&SBPacket::GiveMeMap) {
+                            self.client.borrow_mut().send_binary(bytes).ok();
+                        }
+                    }
+                }
             }
             CBPacket::SyncPlayers(sync_players) => {
                 info!("Processing sync players packet");
                 *game.players.lock() = sync_players.players;
+                *game.host.lock() = sync_players.host;
+                game.update_spectator_button();
             }
 
             CBPacket::SetGameState(game_state) => {
                 info!("Processing set game state packet");
                 *game.game_state.lock() = game_state;
             }
+            CBPacket::MapSeed(seed) => {
+                info!("Map generated from seed {seed}");
+                *game.seed.lock() = Some(seed);
+            }
+            CBPacket::PathRejected(rejected) => {
+                game.handle_path_rejected(rejected.path_id, rejected.reason);
+            }
             CBPacket::MovementConfirmed(movement) => {
                 info!("Processing movement confirmed packet");
                 game.handle_movement_confirmed(movement.path_id, movement.valid_until);
             }
+            CBPacket::RoomList(room_list) => {
+                info!("Processing room list packet");
+                *game.rooms.lock() = room_list.rooms;
+            }
+            CBPacket::ReplayList(replay_list) => {
+                info!("Processing replay list packet");
+                *game.replays.lock() = replay_list.names;
+            }
+            CBPacket::ReplayStarted(replay_started) => {
+                info!("Watching replay {}", replay_started.name);
+                *game.current_room.lock() = None;
+                *game.players.lock() = replay_started.players;
+                game.paths.lock().clear();
+                *game.selected_path.lock() = None;
+                *game.selected_cell.lock() = None;
+                *game.replay.lock() = Some(ReplayState::new(replay_started.name, replay_started.seed, replay_started.total_ticks));
+            }
+            CBPacket::ReplayFrame(frame) => {
+                if let Some(replay) = game.replay.lock().as_mut() {
+                    replay.tick = frame.tick;
+                    replay.playing = frame.playing;
+                    replay.speed = frame.speed;
+                }
+                game.map.lock().replace(frame.map);
+            }
+            CBPacket::ChatMessage(message) => {
+                game.handle_chat_message(message);
+            }
+            CBPacket::ChatRejected(reason) => {
+                info!("Chat message rejected: {reason:?}");
+                game.handle_chat_rejected(reason);
+            }
+            CBPacket::RoomSettings(room_settings) => {
+                info!("Processing room settings packet");
+                *game.settings_bounds.lock() = room_settings.bounds.clone();
+                *game.room_settings.lock() = Some(room_settings);
+            }
+            CBPacket::MapUploaded(name) => {
+                info!("Uploaded map {name}");
+                game.handle_map_uploaded(name);
+            }
+            CBPacket::MapRejected(reason) => {
+                info!("Map upload rejected: {reason}");
+                game.handle_map_rejected(reason);
+            }
+            CBPacket::Kicked => {
+                info!("Kicked from the room");
+                game.handle_kicked();
+            }
+            CBPacket::RoomJoined(room_joined) => {
+                info!("Joined room {}", room_joined.name);
+                *game.current_room.lock() = Some(room_joined.name);
+                *game.following.lock() = None;
+                game.paths.lock().clear();
+                *game.selected_path.lock() = None;
+                *game.selected_cell.lock() = None;
+                *game.map_tick.lock() = None;
+                if let Ok(bytes) = bincode::serialize(&SBPacket::GiveMeMap) {
+                    self.client.borrow_mut().send_binary(bytes)
+                        .map_err(|e| JsValue::from_str(&format!("Failed to send GiveMeMap: {:?}", e))).ok();
+                }
+            }
         }
     }
 }
\ No newline at end of file
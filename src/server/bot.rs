@@ -0,0 +1,181 @@
+use std::collections::{HashMap, VecDeque};
+
+use generals::shared::map::Cell as SharedCell;
+use generals::shared::path::Path;
+use generals::shared::player::BotDifficulty;
+use generals::shared::{MapView, Terrain};
+use uuid::Uuid;
+
+use crate::map::neighbors;
+
+/// Decides where a bot moves next. Strategies only ever get to see the bot's own map view,
+/// so they play under the same fog of war as everyone else.
+pub trait Strategy: Send + Sync {
+    /// Returns the tiles the bot should move along, or `None` to wait for its armies to grow.
+    /// `allies` are the bot and its teammates, whose tiles are never attacked.
+    /// Paths never have more than `max_length` tiles, so the room accepts them.
+    fn next_path(&self, view: &MapView, me: Uuid, allies: &[Uuid], max_length: usize) -> Option<Vec<u32>>;
+}
+
+pub fn strategy_for(difficulty: BotDifficulty) -> Box<dyn Strategy> {
+    match difficulty {
+        BotDifficulty::Expansion => Box::new(Expansion),
+        BotDifficulty::Aggressive => Box::new(Aggressive),
+    }
+}
+
+/// A bot's strategy and the bookkeeping needed to issue paths like a client would.
+pub struct Bot {
+    strategy: Box<dyn Strategy>,
+    next_path_id: u32,
+}
+
+impl Bot {
+    pub fn new(difficulty: BotDifficulty) -> Self {
+        Self {
+            strategy: strategy_for(difficulty),
+            next_path_id: 0,
+        }
+    }
+
+    /// Plans a new path if the bot is not busy walking one.
+    pub fn think(&mut self, view: &MapView, me: Uuid, allies: &[Uuid], paths: &HashMap<u32, Path>, max_length: usize) -> Option<HashMap<u32, Path>> {
+        let busy = paths.values().any(|path| path.valid_until as usize + 1 < path.tile_ids.len());
+        if busy {
+            return None;
+        }
+
+        let tile_ids = self.strategy.next_path(view, me, allies, max_length)?;
+        let path_id = self.next_path_id;
+        self.next_path_id += 1;
+        Some(HashMap::from([(path_id, Path::new(tile_ids))]))
+    }
+}
+
+/// Takes the closest neutral tile its biggest army can beat, fighting only when there is nothing left.
+pub struct Expansion;
+
+impl Strategy for Expansion {
+    fn next_path(&self, view: &MapView, me: Uuid, allies: &[Uuid], max_length: usize) -> Option<Vec<u32>> {
+        let (source, troops) = largest_army(view, me)?;
+        path_to_nearest(view, source, max_length, |cell| cell.owner_id.is_none() && cell.troops + 1 < troops && cell.terrain != Terrain::Swamp)
+            .or_else(|| path_to_nearest(view, source, max_length, |cell| !is_allied(cell, allies) && cell.troops + 1 < troops))
+    }
+}
+
+/// Goes for the closest enemy capital or tile it can see, and expands while it has not found anyone.
+pub struct Aggressive;
+
+impl Strategy for Aggressive {
+    fn next_path(&self, view: &MapView, me: Uuid, allies: &[Uuid], max_length: usize) -> Option<Vec<u32>> {
+        let (source, troops) = largest_army(view, me)?;
+        let is_enemy = |cell: &SharedCell| cell.owner_id.is_some() && !is_allied(cell, allies);
+
+        // Capitals are worth attacking even when they cannot be taken in one go
+        path_to_nearest(view, source, max_length, |cell| is_enemy(cell) && cell.terrain == Terrain::Capital)
+            .or_else(|| path_to_nearest(view, source, max_length, |cell| is_enemy(cell) && cell.troops + 1 < troops))
+            .or_else(|| Expansion.next_path(view, me, allies, max_length))
+    }
+}
+
+fn is_allied(cell: &SharedCell, allies: &[Uuid]) -> bool {
+    cell.owner_id.is_some_and(|owner| allies.contains(&owner))
+}
+
+/// The owned tile with the most troops, if it has any to spare.
+fn largest_army(view: &MapView, me: Uuid) -> Option<(usize, u32)> {
+    view.cells.iter()
+        .filter(|(_, cell)| cell.owner_id == Some(me) && cell.troops > 1)
+        // Break ties on the tile id so bots behave the same every run
+        .max_by_key(|(id, cell)| (cell.troops, std::cmp::Reverse(**id)))
+        .map(|(id, cell)| (*id, cell.troops))
+}
+
+/// Breadth-first search from `source` to the closest visible cell matching `is_target`.
+/// Unknown cells are walked through, known mountains are not, and nothing further than
+/// `max_length` tiles along the way (counting `source`) is considered.
+fn path_to_nearest(view: &MapView, source: usize, max_length: usize, is_target: impl Fn(&SharedCell) -> bool) -> Option<Vec<u32>> {
+    let (width, height) = (view.width, view.height);
+    let mut came_from: HashMap<usize, usize> = HashMap::from([(source, source)]);
+    let mut queue = VecDeque::from([(source, 1)]);
+
+    while let Some((id, length)) = queue.pop_front() {
+        if id != source && view.cells.get(&id).is_some_and(|cell| !cell.fog_of_war && is_target(cell)) {
+            let mut path = vec![id as u32];
+            let mut current = id;
+            while current != source {
+                current = came_from[&current];
+                path.push(current as u32);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        if length >= max_length {
+            continue;
+        }
+
+        for next in neighbors(id, width, height) {
+            if came_from.contains_key(&next) {
+                continue;
+            }
+            if view.cells.get(&next).is_some_and(|cell| cell.terrain == Terrain::Mountain) {
+                continue;
+            }
+            came_from.insert(next, id);
+            queue.push_back((next, length + 1));
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(terrain: Terrain, troops: u32, owner_id: Option<Uuid>) -> SharedCell {
+        SharedCell { terrain, troops, owner_id, fog_of_war: false }
+    }
+
+    #[test]
+    fn strategies_only_target_what_they_can_see() {
+        let me = Uuid::new_v4();
+        let enemy = Uuid::new_v4();
+
+        // A 5x1 strip: our capital on the left, an enemy capital hidden in fog on the right
+        let mut cells = HashMap::new();
+        cells.insert(0, cell(Terrain::Capital, 10, Some(me)));
+        cells.insert(1, cell(Terrain::Default, 0, None));
+        cells.insert(4, SharedCell { fog_of_war: true, ..cell(Terrain::Capital, 0, None) });
+        let mut view = MapView { width: 5, height: 1, cells };
+
+        // Without seeing the enemy both bots just expand next door
+        assert_eq!(Aggressive.next_path(&view, me, &[me], 256), Some(vec![0, 1]));
+        assert_eq!(Expansion.next_path(&view, me, &[me], 256), Some(vec![0, 1]));
+
+        // Once it is in vision the aggressive bot heads straight for the capital
+        view.cells.insert(4, cell(Terrain::Capital, 5, Some(enemy)));
+        assert_eq!(Aggressive.next_path(&view, me, &[me], 256), Some(vec![0, 1, 2, 3, 4]));
+        assert_eq!(Expansion.next_path(&view, me, &[me], 256), Some(vec![0, 1]));
+
+        // Teammates are left alone
+        assert_eq!(Aggressive.next_path(&view, me, &[me, enemy], 256), Some(vec![0, 1]));
+    }
+
+    #[test]
+    fn paths_stay_within_the_length_limit() {
+        let me = Uuid::new_v4();
+        let enemy = Uuid::new_v4();
+
+        let mut cells = HashMap::new();
+        cells.insert(0, cell(Terrain::Capital, 10, Some(me)));
+        cells.insert(1, cell(Terrain::Default, 0, Some(me)));
+        cells.insert(2, cell(Terrain::Default, 0, Some(me)));
+        cells.insert(4, cell(Terrain::Capital, 5, Some(enemy)));
+        let view = MapView { width: 5, height: 1, cells };
+
+        // The capital is five tiles away counting our own, one too many to reach
+        assert_eq!(Aggressive.next_path(&view, me, &[me], 5), Some(vec![0, 1, 2, 3, 4]));
+        assert_eq!(Aggressive.next_path(&view, me, &[me], 4), None);
+    }
+}
@@ -3,27 +3,185 @@ use std::fs;
 use std::path::Path;
 use std::sync::Arc;
 use parking_lot::RwLock;
-use crate::generator::TerrainConfig;
+use crate::generator::{Connectivity, TerrainConfig};
+use generals::shared::settings::{GameSettings, SettingsBounds};
+use generals::shared::Terrain;
 use notify::{RecommendedWatcher, RecursiveMode, Watcher, Config as NotifyConfig};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub terrain_config: TerrainConfig,
     pub tick_ms: f64,
-    pub tile_growth_tick: u32,
-    pub city_growth_tick: u32,
-    pub capital_growth_tick: u32,
+    #[serde(default)]
+    pub growth: GrowthTable,
+    #[serde(default = "default_desert_attrition")]
+    pub desert_attrition: f32,
     pub city_visibility_radius: usize,
     pub tile_visibility_radius: usize,
     pub fow_mountains: bool,
     pub fow_swamps: bool,
     pub disguise_cities_as_mountains: bool,
+    #[serde(default = "default_reconnect_grace_secs")]
+    pub reconnect_grace_secs: u64,
+    #[serde(default = "default_max_paths")]
+    pub max_paths: usize,
+    #[serde(default = "default_max_path_length")]
+    pub max_path_length: usize,
+    #[serde(default = "default_max_chat_length")]
+    pub max_chat_length: usize,
+    #[serde(default = "default_chat_rate_limit")]
+    pub chat_rate_limit: usize,     // Messages a player may send per chat_rate_window_secs
+    #[serde(default = "default_chat_rate_window_secs")]
+    pub chat_rate_window_secs: u64,
+    #[serde(default = "default_min_ready_players")]
+    pub min_ready_players: usize,   // Ready players needed before the host can start, bots included
+    #[serde(default = "default_max_uploads_per_session")]
+    pub max_uploads_per_session: usize,  // Maps a player may upload from the editor per login, 0 turns uploads off
+    #[serde(default = "default_max_maps")]
+    pub max_maps: usize,            // Uploads are refused once the maps directory holds this many maps
+    #[serde(default)]
+    pub settings_bounds: SettingsBounds,  // Limits on the settings hosts choose in the lobby
+    #[serde(default)]
+    pub map: Option<String>,        // Map from the maps directory to play on, generated maps if unset
+}
+
+fn default_desert_attrition() -> f32 {
+    0.25
+}
+
+fn default_reconnect_grace_secs() -> u64 {
+    60
+}
+
+fn default_max_paths() -> usize {
+    32
+}
+
+fn default_max_path_length() -> usize {
+    256
+}
+
+fn default_max_chat_length() -> usize {
+    200
+}
+
+fn default_chat_rate_limit() -> usize {
+    5
+}
+
+fn default_chat_rate_window_secs() -> u64 {
+    10
+}
+
+fn default_min_ready_players() -> usize {
+    2
+}
+
+fn default_max_uploads_per_session() -> usize {
+    3
+}
+
+fn default_max_maps() -> usize {
+    100
+}
+
+/// How the troops on an owned tile change over time.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct GrowthRule {
+    pub interval: u32,  // Ticks between changes, 0 disables the rule
+    pub amount: i32,    // Troops added per change, negative values decay the tile
+}
+
+/// Growth rule for every terrain that can be owned.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GrowthTable {
+    pub capital: GrowthRule,
+    pub city: GrowthRule,
+    pub plain: GrowthRule,
+    pub desert: GrowthRule,
+    pub swamp: GrowthRule,
+}
+
+impl Default for GrowthTable {
+    fn default() -> Self {
+        Self {
+            capital: GrowthRule { interval: 5, amount: 1 },
+            city: GrowthRule { interval: 10, amount: 1 },
+            plain: GrowthRule { interval: 25, amount: 1 },
+            desert: GrowthRule { interval: 25, amount: 0 },
+            swamp: GrowthRule { interval: 10, amount: -1 },
+        }
+    }
+}
+
+impl GrowthTable {
+    pub fn rule(&self, terrain: Terrain) -> Option<GrowthRule> {
+        match terrain {
+            Terrain::Capital => Some(self.capital),
+            Terrain::City => Some(self.city),
+            Terrain::Default => Some(self.plain),
+            Terrain::Desert => Some(self.desert),
+            Terrain::Swamp => Some(self.swamp),
+            Terrain::Mountain => None,
+        }
+    }
+}
+
+/// Growth keys of configs written before the growth table existed.
+#[derive(Debug, Default, Deserialize)]
+struct LegacyGrowth {
+    tile_growth_tick: Option<u32>,
+    city_growth_tick: Option<u32>,
+    capital_growth_tick: Option<u32>,
+    desert_growth: Option<bool>,
+}
+
+impl LegacyGrowth {
+    fn is_empty(&self) -> bool {
+        self.tile_growth_tick.is_none() && self.city_growth_tick.is_none()
+            && self.capital_growth_tick.is_none() && self.desert_growth.is_none()
+    }
+
+    /// Carries the old intervals over into the table, every tile class grew by one troop back then.
+    /// Capitals used to grow with the cities, so they follow `city_growth_tick` unless they have their own.
+    fn apply(&self, growth: &mut GrowthTable) {
+        if let Some(interval) = self.capital_growth_tick.or(self.city_growth_tick) {
+            growth.capital = GrowthRule { interval, amount: 1 };
+        }
+        if let Some(interval) = self.city_growth_tick {
+            growth.city = GrowthRule { interval, amount: 1 };
+        }
+        if let Some(interval) = self.tile_growth_tick {
+            growth.plain = GrowthRule { interval, amount: 1 };
+        }
+        if let Some(desert_growth) = self.desert_growth {
+            growth.desert = GrowthRule { interval: growth.plain.interval, amount: i32::from(desert_growth) };
+        }
+    }
 }
 
 impl Config {
     pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
         let contents = fs::read_to_string(path)?;
-        let config: Config = toml::from_str(&contents)?;
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let table: toml::Table = toml::from_str(contents)?;
+        let has_growth_table = table.contains_key("growth");
+        let legacy: LegacyGrowth = table.clone().try_into()?;
+        let mut config: Config = table.try_into()?;
+
+        // Older configs keep working, an explicit [growth] table wins over the old keys
+        if !legacy.is_empty() {
+            if has_growth_table {
+                println!("Config has both a [growth] table and old growth keys, the old keys are ignored");
+            } else {
+                println!("Config uses the old growth keys, consider moving them to a [growth] table");
+                legacy.apply(&mut config.growth);
+            }
+        }
         Ok(config)
     }
 
@@ -37,18 +195,64 @@ impl Config {
                 clustering_factor: 0.7,
                 map_width: 20,
                 map_height: 20,
+                seed: None,
+                min_capital_distance: 8,
+                connectivity: Connectivity::Carve,
             },
             tick_ms: 500.0,
-            tile_growth_tick: 25,
-            city_growth_tick: 10,
-            capital_growth_tick: 5,
+            growth: GrowthTable::default(),
+            desert_attrition: default_desert_attrition(),
             city_visibility_radius: 3,
             tile_visibility_radius: 1,
             fow_mountains: false,
             fow_swamps: false,
             disguise_cities_as_mountains: false,
+            reconnect_grace_secs: default_reconnect_grace_secs(),
+            max_paths: default_max_paths(),
+            max_path_length: default_max_path_length(),
+            max_chat_length: default_max_chat_length(),
+            chat_rate_limit: default_chat_rate_limit(),
+            chat_rate_window_secs: default_chat_rate_window_secs(),
+            min_ready_players: default_min_ready_players(),
+            max_uploads_per_session: default_max_uploads_per_session(),
+            max_maps: default_max_maps(),
+            settings_bounds: SettingsBounds::default(),
+            map: None,
         }
     }
+
+    /// The values a host can change in the lobby.
+    pub fn settings(&self) -> GameSettings {
+        GameSettings {
+            map_width: self.terrain_config.map_width,
+            map_height: self.terrain_config.map_height,
+            mountain_density: self.terrain_config.mountain_density,
+            desert_density: self.terrain_config.desert_density,
+            swamp_density: self.terrain_config.swamp_density,
+            city_density: self.terrain_config.city_density,
+            tick_ms: self.tick_ms,
+            fow_mountains: self.fow_mountains,
+            fow_swamps: self.fow_swamps,
+            disguise_cities_as_mountains: self.disguise_cities_as_mountains,
+            map: self.map.clone(),
+        }
+    }
+
+    /// Overrides the config with a host's settings, kept within the bounds.
+    pub fn apply_settings(&mut self, settings: &GameSettings) {
+        let settings = settings.clone().clamp(&self.settings_bounds);
+        self.terrain_config.map_width = settings.map_width;
+        self.terrain_config.map_height = settings.map_height;
+        self.terrain_config.mountain_density = settings.mountain_density;
+        self.terrain_config.desert_density = settings.desert_density;
+        self.terrain_config.swamp_density = settings.swamp_density;
+        self.terrain_config.city_density = settings.city_density;
+        self.tick_ms = settings.tick_ms;
+        self.fow_mountains = settings.fow_mountains;
+        self.fow_swamps = settings.fow_swamps;
+        self.disguise_cities_as_mountains = settings.disguise_cities_as_mountains;
+        self.map = settings.map;
+    }
 }
 
 pub type SharedConfig = Arc<RwLock<Config>>;
@@ -119,3 +323,89 @@ pub fn create_shared_config(path: Option<impl AsRef<Path> + Clone>) -> SharedCon
 
     shared_config
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_growth_keys_fill_the_growth_table() {
+        let config = Config::parse(r#"
+            tick_ms = 500.0
+            tile_growth_tick = 20
+            city_growth_tick = 4
+            capital_growth_tick = 2
+            desert_growth = true
+            desert_attrition = 0.25
+            city_visibility_radius = 1
+            tile_visibility_radius = 1
+            fow_mountains = false
+            fow_swamps = false
+            disguise_cities_as_mountains = true
+            reconnect_grace_secs = 60
+            max_paths = 32
+            max_path_length = 256
+            max_chat_length = 200
+            chat_rate_limit = 5
+            chat_rate_window_secs = 10
+            min_ready_players = 2
+
+            [terrain_config]
+            mountain_density = 0.12
+            desert_density = 0.15
+            swamp_density = 0.08
+            city_density = 0.04
+            clustering_factor = 0.2
+            map_width = 30
+            map_height = 30
+        "#).unwrap();
+
+        let growth = &config.growth;
+        assert_eq!((growth.capital.interval, growth.capital.amount), (2, 1));
+        assert_eq!((growth.city.interval, growth.city.amount), (4, 1));
+        assert_eq!((growth.plain.interval, growth.plain.amount), (20, 1));
+        assert_eq!((growth.desert.interval, growth.desert.amount), (20, 1));
+        // Swamps had no key of their own and keep decaying
+        assert_eq!(growth.swamp.amount, GrowthTable::default().swamp.amount);
+    }
+
+    #[test]
+    fn configs_from_before_the_new_settings_still_load() {
+        let config = Config::parse(r#"
+            tick_ms = 500.0
+            tile_growth_tick = 25
+            city_growth_tick = 2
+            capital_growth_tick = 2
+            city_visibility_radius = 1
+            tile_visibility_radius = 1
+            fow_mountains = false
+            fow_swamps = false
+            disguise_cities_as_mountains = true
+
+            [terrain_config]
+            mountain_density = 0.12
+            desert_density = 0.15
+            swamp_density = 0.08
+            city_density = 0.04
+            clustering_factor = 0.2
+            map_width = 30
+            map_height = 30
+        "#).unwrap();
+
+        let defaults = Config::default();
+        assert_eq!(config.reconnect_grace_secs, defaults.reconnect_grace_secs);
+        assert_eq!(config.max_paths, defaults.max_paths);
+        assert_eq!(config.max_chat_length, defaults.max_chat_length);
+        assert_eq!(config.min_ready_players, defaults.min_ready_players);
+        assert_eq!(config.max_uploads_per_session, defaults.max_uploads_per_session);
+        assert_eq!(config.max_maps, defaults.max_maps);
+        assert_eq!(config.growth.capital.interval, 2);
+    }
+
+    #[test]
+    fn growth_table_wins_over_old_keys() {
+        let config = Config::parse(&format!("capital_growth_tick = 7\n{}", fs::read_to_string("config.toml").unwrap())).unwrap();
+        let bundled = Config::load("config.toml").unwrap();
+        assert_eq!(config.growth.capital.interval, bundled.growth.capital.interval);
+    }
+}
@@ -1,6 +1,6 @@
 use rand::Rng;
 use generals::shared::terrain::Terrain;
-use crate::map::{Map, Cell};
+use crate::map::{neighbors, passable_regions, Cell};
 
 use serde::{Deserialize, Serialize};
 
@@ -13,6 +13,32 @@ pub struct TerrainConfig {
     pub clustering_factor: f32,  // 0.0 to 1.0, how much terrain should cluster together
     pub map_width: usize,       // Width of the game map
     pub map_height: usize,      // Height of the game map
+    #[serde(default)]
+    pub seed: Option<u64>,      // Fixed seed for every game, a random one is picked per game if unset
+    #[serde(default = "default_min_capital_distance")]
+    pub min_capital_distance: usize,  // Minimum number of steps between two capitals
+    #[serde(default)]
+    pub connectivity: Connectivity,  // How maps split up by mountains are fixed
+}
+
+/// What to do when mountains cut the map into areas that cannot reach each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Connectivity {
+    // Leave the map as generated
+    Off,
+    // Dig through the fewest mountains needed to join the areas up
+    #[default]
+    Carve,
+    // Generate new terrain until the map is connected, carving if that keeps failing
+    Regenerate,
+}
+
+/// Attempts at a connected map before `Connectivity::Regenerate` falls back to carving
+const REGENERATE_ATTEMPTS: usize = 20;
+
+fn default_min_capital_distance() -> usize {
+    8
 }
 
 impl Default for TerrainConfig {
@@ -25,12 +51,90 @@ impl Default for TerrainConfig {
             clustering_factor: 0.7,    // High clustering
             map_width: 20,           // Default map width
             map_height: 20,          // Default map height
+            seed: None,              // Random seed per game
+            min_capital_distance: default_min_capital_distance(),
+            connectivity: Connectivity::default(),
+        }
+    }
+}
+
+pub fn generate_map_tiles(width: usize, height: usize, config: &TerrainConfig, rng: &mut impl Rng) -> Vec<Cell> {
+    let mut cells = place_terrain_tiles(width, height, config, rng);
+
+    match config.connectivity {
+        Connectivity::Off => {}
+        Connectivity::Carve => carve_passes(&mut cells, width, height),
+        Connectivity::Regenerate => {
+            let mut attempts = 1;
+            while !is_connected(&cells, width, height) && attempts < REGENERATE_ATTEMPTS {
+                cells = place_terrain_tiles(width, height, config, rng);
+                attempts += 1;
+            }
+            carve_passes(&mut cells, width, height);
+        }
+    }
+
+    cells
+}
+
+/// True if every tile that is not a mountain can be reached from every other one.
+pub fn is_connected(cells: &[Cell], width: usize, height: usize) -> bool {
+    passable_regions(cells, width, height).iter().flatten().all(|&region| region == 0)
+}
+
+/// Joins every area cut off by mountains to the largest one by turning the mountains on the
+/// shortest way between them into plain tiles.
+fn carve_passes(cells: &mut [Cell], width: usize, height: usize) {
+    loop {
+        let regions = passable_regions(cells, width, height);
+        let mut sizes = std::collections::HashMap::new();
+        for &region in regions.iter().flatten() {
+            *sizes.entry(region).or_insert(0usize) += 1;
+        }
+        if sizes.len() <= 1 {
+            return;
+        }
+        let main = sizes.iter()
+            .max_by_key(|&(&region, &size)| (size, std::cmp::Reverse(region)))
+            .map(|(&region, _)| region)
+            .unwrap_or(0);
+
+        // Search outwards from the main area until another area is hit
+        let mut came_from = vec![None; cells.len()];
+        let mut queue: std::collections::VecDeque<usize> = (0..cells.len())
+            .filter(|&id| regions[id] == Some(main))
+            .collect();
+        for &id in &queue {
+            came_from[id] = Some(id);
+        }
+
+        let mut reached = None;
+        while let Some(id) = queue.pop_front() {
+            if regions[id].is_some_and(|region| region != main) {
+                reached = Some(id);
+                break;
+            }
+            for next in neighbors(id, width, height) {
+                if came_from[next].is_none() {
+                    came_from[next] = Some(id);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        let Some(mut id) = reached else {
+            return;
+        };
+        while let Some(previous) = came_from[id].filter(|&previous| previous != id) {
+            if cells[previous].terrain == Terrain::Mountain {
+                cells[previous].terrain = Terrain::Default;
+            }
+            id = previous;
         }
     }
 }
 
-pub fn generate_map_tiles(width: usize, height: usize, config: &TerrainConfig) -> Vec<Cell> {
-    let mut rng = rand::thread_rng();
+fn place_terrain_tiles(width: usize, height: usize, config: &TerrainConfig, rng: &mut impl Rng) -> Vec<Cell> {
     let total_cells = width * height;
     let mut cells = vec![Cell::default(); total_cells];
 
@@ -114,26 +218,19 @@ pub fn generate_map_tiles(width: usize, height: usize, config: &TerrainConfig) -
     cells
 }
 
-pub fn generate_map(width: usize, height: usize, config: TerrainConfig) -> Map {
-    let map = Map::new(width, height);
-    let tiles = generate_map_tiles(width, height, &config);
-    {
-        let mut cells = map.cells.write();
-        cells.clone_from_slice(&tiles);
-    }
-    map
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::shared::terrain::Terrain;
+    use rand::{rngs::StdRng, SeedableRng};
 
     #[test]
     fn test_terrain_generation() {
-        let config = TerrainConfig::default();
-        let map = generate_map(20, 20, config);
-        let cells = map.cells.read();
+        // Carving passes would take mountains away again
+        let config = TerrainConfig {
+            connectivity: Connectivity::Off,
+            ..TerrainConfig::default()
+        };
+        let cells = generate_map_tiles(20, 20, &config, &mut rand::thread_rng());
 
         // Count each terrain type
         let mut counts = std::collections::HashMap::new();
@@ -156,4 +253,43 @@ mod tests {
         let city_count = *counts.get(&Terrain::City).unwrap_or(&0) as f32 / total_cells;
         assert!((city_count - config.city_density).abs() < 0.02);
     }
+
+    #[test]
+    fn test_same_seed_same_map() {
+        let config = TerrainConfig::default();
+        let terrain = |seed| -> Vec<(Terrain, u32)> {
+            generate_map_tiles(20, 20, &config, &mut StdRng::seed_from_u64(seed))
+                .iter()
+                .map(|cell| (cell.terrain, cell.troops))
+                .collect()
+        };
+
+        assert_eq!(terrain(42), terrain(42));
+        assert_ne!(terrain(42), terrain(43));
+    }
+
+    #[test]
+    fn test_maps_are_connected() {
+        // Mountain heavy settings that regularly wall off parts of the map
+        let mut config = TerrainConfig {
+            mountain_density: 0.2,
+            clustering_factor: 0.5,
+            ..TerrainConfig::default()
+        };
+
+        // Make sure the settings actually produce split maps when left alone
+        config.connectivity = Connectivity::Off;
+        let split = (0..100)
+            .filter(|&seed| !is_connected(&generate_map_tiles(25, 20, &config, &mut StdRng::seed_from_u64(seed)), 25, 20))
+            .count();
+        assert!(split > 0);
+
+        for connectivity in [Connectivity::Carve, Connectivity::Regenerate] {
+            config.connectivity = connectivity;
+            for seed in 0..100 {
+                let cells = generate_map_tiles(25, 20, &config, &mut StdRng::seed_from_u64(seed));
+                assert!(is_connected(&cells, 25, 20), "seed {seed} with {connectivity:?} is split up");
+            }
+        }
+    }
 }
\ No newline at end of file
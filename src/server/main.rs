@@ -1,10 +1,17 @@
 mod player;
+mod bot;
 mod map;
 mod tick;
+mod sim;
 mod generator;
 mod config;
+mod room;
+mod session;
+mod replay;
+mod map_file;
 
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
@@ -12,16 +19,18 @@ use axum::response::IntoResponse;
 use axum::routing::get;
 use axum::Router;
 use futures_util::StreamExt;
-use generals::shared::cb_packet::LoginAccepted;
-use generals::shared::game_state::GameState;
+use generals::shared::cb_packet::{ChatMessage, ChatRejectReason, LoginAccepted, RoomJoined, RoomList};
+use generals::shared::sb_packet::{Chat, ChatScope, CreateRoom, JoinRoom, ReplayControl, UploadMap};
+use generals::shared::settings::GameSettings;
 use generals::shared::{CBPacket, Color, SBPacket};
 use parking_lot::RwLock;
 
 use uuid::Uuid;
 
-use crate::map::Map;
 use crate::player::Player;
-use generator::{generate_map, generate_map_tiles};
+use crate::replay::ReplayViewer;
+use crate::room::Room;
+use crate::session::Session;
 
 async fn ws_handler(ws: WebSocketUpgrade, server: Arc<Server>) -> impl IntoResponse {
     ws.on_upgrade(move |socket| handle_socket(socket, server.clone()))
@@ -32,72 +41,146 @@ async fn handle_socket(socket: WebSocket, server: Arc<Server>) {
     let (write, mut read) = socket.split();
 
     // Create a temporary player for the connection
-    let player_id = Uuid::new_v4();
-    let player = Arc::new(Player::new(
-        player_id,
+    let mut player = Arc::new(Player::new(
+        Uuid::new_v4(),
         "Connecting...".to_string(),
         Color { r: 0, g: 128, b: 255, a: 255 },
-        write
+        player::socket_sender(write)
     ));
 
     // Handle player disconnect when the loop ends
-    let _cleanup = CleanupGuard {
+    let mut cleanup = CleanupGuard {
         server: server.clone(),
-        player_id,
+        session: None,
+        connection: player.connection(),
     };
 
     while let Some(Ok(msg)) = read.next().await {
         if let Message::Binary(data) = msg {
             match bincode::deserialize::<SBPacket>(&data) {
+                Ok(SBPacket::Login(_) | SBPacket::Resume(_)) if cleanup.session.is_some() => {
+                    println!("Received login packet from already logged in player {}", player.name.read());
+                }
                 Ok(SBPacket::Login(login)) => {
                     // Update player info
                     *player.name.write() = login.username.clone();
                     if let Some(color) = login.color_bid {
                         *player.color.write() = color;
                     }
+                    println!("Player with username {} logged in", player.name.read());
 
-                    // Check if game is already in progress
-                    let current_state = *server.game_state.read();
-                    if current_state == GameState::InGame {
-                        *player.alive.write() = false;
-                    } else {
-                        server.map.add_player_capital(player_id);
-                    }
+                    let session = Arc::new(Session::new(player.clone()));
+                    server.sessions.write().insert(session.token, session.clone());
+                    server.send_login_accepted(&session);
 
-                    server.players.write().insert(player_id, player.clone());
-                    println!("Player with username {} logged in", player.name.read());
-                    server.sync_map();
-
-                    // Send login accepted
-                    let accepted = CBPacket::LoginAccepted(LoginAccepted {
-                        player_id,
-                        color: *player.color.read(),
-                    });
-                    if let Ok(resp) = bincode::serialize(&accepted) {
+                    // Let the player pick a room
+                    server.send_room_list(&player);
+                    cleanup.session = Some(session);
+                }
+                Ok(SBPacket::Resume(resume)) => {
+                    let Some(session) = server.sessions.read().get(&resume.session_token).cloned() else {
+                        println!("Rejected resume with unknown session token");
+                        if let Ok(resp) = bincode::serialize(&CBPacket::ResumeRejected) {
+                            player.send_bytes(resp);
+                        }
+                        continue;
+                    };
+
+                    // Route the existing player's traffic through this connection
+                    cleanup.connection = session.player.rebind(&player);
+                    player = session.player.clone();
+                    println!("Player with username {} resumed their session", player.name.read());
+
+                    server.send_login_accepted(&session);
+                    server.send_room_list(&player);
+                    if let Some(room) = session.room() {
+                        room.resume_player(&player);
+                    }
+                    cleanup.session = Some(session);
+                }
+                Ok(SBPacket::ListRooms) => {
+                    server.send_room_list(&player);
+                }
+                Ok(SBPacket::CreateRoom(CreateRoom { name }) | SBPacket::JoinRoom(JoinRoom { name, .. })) if cleanup.session.is_none() => {
+                    println!("Player tried to enter room {name} before logging in");
+                }
+                Ok(SBPacket::CreateRoom(CreateRoom { name })) => {
+                    let name = name.trim().to_string();
+                    let Some(room) = server.create_room(name.clone()) else {
+                        println!("Room {name} could not be created");
+                        server.send_room_list(&player);
+                        continue;
+                    };
+                    if let Some(session) = &cleanup.session {
+                        server.join_room(session, room, false);
+                    }
+                }
+                Ok(SBPacket::JoinRoom(JoinRoom { name, spectate })) => {
+                    let Some(room) = server.rooms.read().get(&name).cloned() else {
+                        println!("Room {name} does not exist");
+                        server.send_room_list(&player);
+                        continue;
+                    };
+                    if let Some(session) = &cleanup.session {
+                        server.join_room(session, room, spectate);
+                    }
+                }
+                Ok(SBPacket::ListReplays) => {
+                    if let Ok(resp) = bincode::serialize(&replay::list_replays(&server.replay_dir)) {
                         player.send_bytes(resp);
                     }
-
-                    // Send player sync with all players
-                    let all_players: Vec<_> = server.players.read().values().map(|p| p.to_view()).collect();
-                    let sync = CBPacket::SyncPlayers(generals::shared::cb_packet::SyncPlayers {
-                        players: all_players
-                    });
-                    if let Ok(resp) = bincode::serialize(&sync) {
-                        // Send to all connected players
-                        for p in server.players.read().values() {
-                            p.send_bytes(resp.clone());
+                }
+                Ok(SBPacket::WatchReplay(watch)) => {
+                    let Some(session) = &cleanup.session else {
+                        continue;
+                    };
+                    match ReplayViewer::open(&server.replay_dir, &watch.name, player.clone()).await {
+                        Ok(viewer) => {
+                            // Watching a replay takes the player out of their room
+                            let room = session.room.write().take();
+                            if let Some(room) = room {
+                                server.leave_room(&room, player.id());
+                            }
+                            *session.replay.write() = Some(viewer);
                         }
+                        Err(e) => eprintln!("Failed to open replay {}: {e}", watch.name),
                     }
-
-                    // Send current game state to the new player
-                    let game_state = *server.game_state.read();
-                    let state_packet = CBPacket::SetGameState(game_state);
-                    if let Ok(resp) = bincode::serialize(&state_packet) {
-                        player.send_bytes(resp);
+                }
+                Ok(SBPacket::ReplayControl(control)) => {
+                    let Some(session) = &cleanup.session else {
+                        continue;
+                    };
+                    if let ReplayControl::Stop = control {
+                        session.replay.write().take();
+                        server.send_room_list(&player);
+                    } else if let Some(viewer) = session.replay() {
+                        viewer.control(control);
+                    }
+                }
+                Ok(SBPacket::Chat(chat)) => {
+                    if let Some(session) = &cleanup.session {
+                        server.chat(session, chat);
+                    }
+                }
+                Ok(SBPacket::UploadMap(upload)) => {
+                    if let Some(session) = &cleanup.session {
+                        server.upload_map(session, upload);
+                    }
+                }
+                Ok(SBPacket::LeaveRoom) => {
+                    if let Some(session) = &cleanup.session {
+                        server.leave(session);
+                    }
+                }
+                Ok(SBPacket::KickPlayer(kick)) => {
+                    if let Some(session) = &cleanup.session {
+                        server.kick(session, kick.player_id);
                     }
                 }
                 Ok(other) => {
-                    player.handle_packet(other, &server).await;
+                    if let Some(room) = cleanup.session.as_ref().and_then(|session| session.room()) {
+                        player.handle_packet(other, &room).await;
+                    }
                 }
                 Err(err) => eprintln!("bad packet: {err}"),
             }
@@ -107,106 +190,277 @@ async fn handle_socket(socket: WebSocket, server: Arc<Server>) {
 
 struct CleanupGuard {
     server: Arc<Server>,
-    player_id: Uuid,
+    session: Option<Arc<Session>>,
+    connection: u32,
 }
 
 impl Drop for CleanupGuard {
     fn drop(&mut self) {
-        // When a player disconnects, remove them from the game
-        self.server.remove_player(self.player_id);
+        // When a player disconnects, hold their spot until the grace period runs out
+        if let Some(session) = self.session.take() {
+            self.server.disconnect(session, self.connection);
+        }
     }
 }
 
 struct Server {
-    players: RwLock<HashMap<Uuid, Arc<Player>>>,
-    map: Arc<Map>,
-    game_state: RwLock<GameState>,
-    tick_counter: RwLock<u32>,
+    rooms: RwLock<HashMap<String, Arc<Room>>>,
+    sessions: RwLock<HashMap<Uuid, Arc<Session>>>,
     config: config::SharedConfig,
+    replay_dir: PathBuf,
 }
 
 impl Server {
-    fn remove_player(&self, player_id: Uuid) {
-        // Remove player from the map
-        self.map.remove_player(player_id);
-
-        // Remove player from the players list
-        self.players.write().remove(&player_id);
+    fn new(config: config::SharedConfig, replay_dir: PathBuf) -> Self {
+        Self {
+            rooms: RwLock::new(HashMap::new()),
+            sessions: RwLock::new(HashMap::new()),
+            config,
+            replay_dir,
+        }
+    }
 
-        // Notify remaining players about the player list change
-        let all_players: Vec<_> = self.players.read().values().map(|p| p.to_view()).collect();
-        let sync = CBPacket::SyncPlayers(generals::shared::cb_packet::SyncPlayers {
-            players: all_players
+    fn send_login_accepted(&self, session: &Session) {
+        let accepted = CBPacket::LoginAccepted(LoginAccepted {
+            player_id: session.player.id(),
+            color: *session.player.color.read(),
+            session_token: session.token,
+            settings_bounds: self.config.read().settings_bounds.clone(),
         });
-        if let Ok(resp) = bincode::serialize(&sync) {
-            // Send to all remaining players
-            for p in self.players.read().values() {
-                p.send_bytes(resp.clone());
+        if let Ok(resp) = bincode::serialize(&accepted) {
+            session.player.send_bytes(resp);
+        }
+    }
+
+    fn disconnect(self: &Arc<Self>, session: Arc<Session>, connection: u32) {
+        // Nothing to do if the session was already resumed on another connection
+        if !session.player.disconnect(connection) {
+            return;
+        }
+        if let Some(room) = session.room() {
+            room.sync_players();
+        }
+
+        let grace_secs = self.config.read().reconnect_grace_secs;
+        let server = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(tokio::time::Duration::from_secs(grace_secs)).await;
+
+            // A resume in the meantime starts a new connection, which keeps the session alive
+            if session.player.connection() != connection || *session.player.connected.read() {
+                return;
             }
+
+            println!("Session of player {} expired", session.player.name.read());
+            server.sessions.write().remove(&session.token);
+            let room = session.room.write().take();
+            if let Some(room) = room {
+                server.leave_room(&room, session.player.id());
+            }
+        });
+    }
+
+    /// Creates a room and starts its tick loop. Returns `None` if the name is empty or taken.
+    fn create_room(&self, name: String) -> Option<Arc<Room>> {
+        if name.is_empty() {
+            return None;
+        }
+
+        let mut rooms = self.rooms.write();
+        if rooms.contains_key(&name) {
+            return None;
         }
 
-        // Sync map to show territory changes
-        self.sync_map();
+        let room = Arc::new(Room::new(name.clone(), self.config.clone(), Some(self.replay_dir.clone())));
+        room::spawn_tick_loop(&room);
+        rooms.insert(name.clone(), room.clone());
+        drop(rooms);
+
+        println!("Room {name} created");
+        self.broadcast_room_list();
+        Some(room)
     }
 
-    fn new(map: Map, config: config::SharedConfig) -> Self {
-        Self {
-            players: RwLock::new(HashMap::new()),
-            map: Arc::new(map),
-            game_state: RwLock::new(GameState::Lobby),
-            tick_counter: RwLock::new(0),
-            config,
+    fn join_room(&self, session: &Session, room: Arc<Room>, spectate: bool) {
+        let player = &session.player;
+        if room.is_kicked(player.id()) {
+            println!("Player {} was kicked from room {} and cannot rejoin", player.name.read(), room.name);
+            self.send_room_list(player);
+            return;
+        }
+        if let Some(old_room) = session.room() {
+            if Arc::ptr_eq(&old_room, &room) {
+                return;
+            }
+            self.leave_room(&old_room, player.id());
         }
+
+        session.replay.write().take();
+        room.add_player(player.clone(), spectate);
+        *session.room.write() = Some(room.clone());
+
+        let joined = CBPacket::RoomJoined(RoomJoined { name: room.name.clone() });
+        if let Ok(resp) = bincode::serialize(&joined) {
+            player.send_bytes(resp);
+        }
+
+        self.broadcast_room_list();
     }
 
-    pub fn sync_map(&self) {
-        let players = self.players.read();
-        for player in players.values() {
-            let map_view = self.map.to_map_view(player.id(), self);
-            let packet = generals::shared::CBPacket::MapSync(generals::shared::cb_packet::MapSync { map: map_view });
+    fn leave_room(&self, room: &Arc<Room>, player_id: Uuid) {
+        room.remove_player(player_id);
 
-            if let Ok(bytes) = bincode::serialize(&packet) {
-                player.send_bytes(bytes);
+        // Close rooms once everyone has left; the tick loop stops with the last reference
+        if room.is_empty() {
+            let mut rooms = self.rooms.write();
+            if rooms.get(&room.name).is_some_and(|r| Arc::ptr_eq(r, room)) {
+                rooms.remove(&room.name);
+                room.close();
+                println!("Room {} closed", room.name);
             }
         }
+
+        self.broadcast_room_list();
+    }
+
+    /// Takes the session's player out of their room and back to the room list.
+    fn leave(&self, session: &Session) {
+        let room = session.room.write().take();
+        match room {
+            // The player is browsing again, so the room list update reaches them
+            Some(room) => self.leave_room(&room, session.player.id()),
+            None => self.send_room_list(&session.player),
+        }
+    }
+
+    /// Lets the host of the session's room kick a player, who is sent back to the room list.
+    fn kick(&self, session: &Session, player_id: Uuid) {
+        let Some(room) = session.room() else {
+            return;
+        };
+        if !room.kick(&session.player, player_id) {
+            return;
+        }
+
+        let target = self.sessions.read().values().find(|s| s.player.id() == player_id).cloned();
+        let Some(target) = target else {
+            // No session left to update, the player only has to leave the room
+            self.leave_room(&room, player_id);
+            return;
+        };
+        if target.room().is_some_and(|r| Arc::ptr_eq(&r, &room)) {
+            target.room.write().take();
+            self.leave_room(&room, player_id);
+        }
+        if let Ok(resp) = bincode::serialize(&CBPacket::Kicked) {
+            target.player.send_bytes(resp);
+        }
+        self.send_room_list(&target.player);
     }
 
-    pub fn set_game_state(&self, new_state: GameState) {
-        // Update server's game state
-        *self.game_state.write() = new_state;
+    /// Saves a map from the editor and opens a room on it, so the uploader can play it right away.
+    fn upload_map(&self, session: &Session, upload: UploadMap) {
+        let player = &session.player;
+        let (bounds, max_uploads, max_maps) = {
+            let config = self.config.read();
+            (config.settings_bounds.clone(), config.max_uploads_per_session, config.max_maps)
+        };
+        let result = match *session.uploads.read() {
+            _ if max_uploads == 0 => Err("map uploads are turned off on this server".to_string()),
+            uploads if uploads >= max_uploads => Err(format!("you can upload at most {max_uploads} maps")),
+            _ => map_file::save_upload(&upload.name, &upload.map, &bounds, max_maps),
+        };
+        if result.is_ok() {
+            *session.uploads.write() += 1;
+        }
+
+        let reply = match &result {
+            Ok(()) => {
+                println!("Player {} uploaded map {}", player.name.read(), upload.name);
+                CBPacket::MapUploaded(upload.name.clone())
+            }
+            Err(reason) => {
+                println!("Rejected map {} from player {}: {reason}", upload.name, player.name.read());
+                CBPacket::MapRejected(reason.clone())
+            }
+        };
+        if let Ok(resp) = bincode::serialize(&reply) {
+            player.send_bytes(resp);
+        }
+        if result.is_err() {
+            return;
+        }
+
+        // The room is named after the map, if that name is taken the map can still be picked in any room
+        let Some(room) = self.create_room(upload.name.clone()) else {
+            return;
+        };
+        self.join_room(session, room.clone(), false);
+        let settings = GameSettings { map: Some(upload.name), ..room.config.read().settings() };
+        room.set_settings(player, settings);
+    }
 
-        // Create packet to notify clients
-        let packet = CBPacket::SetGameState(new_state);
+    /// Checks a chat message against the limits and delivers it, telling the sender if it was dropped.
+    fn chat(&self, session: &Session, chat: Chat) {
+        let player = &session.player;
+        let now = std::time::Instant::now();
+        let result = player.check_chat(&chat.text, &self.config.read(), now)
+            .and_then(|text| {
+                let delivered = match (chat.scope, session.room()) {
+                    (ChatScope::Lobby, None) => {
+                        self.send_lobby_chat(player, text);
+                        true
+                    }
+                    (ChatScope::Lobby, Some(_)) | (_, None) => false,
+                    (scope, Some(room)) => room.send_chat(player, scope, text),
+                };
+                if delivered { Ok(()) } else { Err(ChatRejectReason::NoAudience) }
+            });
+
+        // Only messages that reached someone count towards the rate limit
+        if result.is_ok() {
+            player.record_chat(now);
+        }
+        if let Err(reason) = result
+            && let Ok(resp) = bincode::serialize(&CBPacket::ChatRejected(reason)) {
+            player.send_bytes(resp);
+        }
+    }
 
-        // Serialize and send to all players
-        if let Ok(bytes) = bincode::serialize(&packet) {
-            for player in self.players.read().values() {
-                player.send_bytes(bytes.clone());
+    /// Sends a chat message to every logged in player who is not in a room.
+    fn send_lobby_chat(&self, sender: &Player, text: String) {
+        let message = CBPacket::ChatMessage(ChatMessage {
+            scope: ChatScope::Lobby,
+            sender: sender.id(),
+            name: sender.name.read().clone(),
+            text,
+        });
+        if let Ok(resp) = bincode::serialize(&message) {
+            for session in self.sessions.read().values().filter(|session| session.room().is_none()) {
+                session.player.send_bytes(resp.clone());
             }
         }
     }
 
-    pub fn reset_map(&self) {
-        // Reset the map cells
-        let mut cells = self.map.cells.write();
-        let config = self.config.read().terrain_config.clone();
-        *cells = generate_map_tiles(
-            self.map.width,
-            self.map.height,
-            &config
-        );
-        drop(cells); // Release the write lock before adding capitals
-
-        // Reset player states and add capitals
-        let players = self.players.read();
-        for player in players.values() {
-            *player.alive.write() = true;
-            player.paths.write().clear();
-            self.map.add_player_capital(player.id());
+    fn room_list(&self) -> CBPacket {
+        let mut rooms: Vec<_> = self.rooms.read().values().map(|room| room.to_view()).collect();
+        rooms.sort_by(|a, b| a.name.cmp(&b.name));
+        CBPacket::RoomList(RoomList { rooms })
+    }
+
+    fn send_room_list(&self, player: &Player) {
+        if let Ok(resp) = bincode::serialize(&self.room_list()) {
+            player.send_bytes(resp);
         }
+    }
 
-        // Sync the updated map to all players
-        self.sync_map();
+    /// Sends the room list to every logged in player who is browsing it rather than sitting in a room.
+    fn broadcast_room_list(&self) {
+        if let Ok(resp) = bincode::serialize(&self.room_list()) {
+            for session in self.sessions.read().values().filter(|session| session.room().is_none()) {
+                session.player.send_bytes(resp.clone());
+            }
+        }
     }
 }
 
@@ -215,44 +469,10 @@ async fn main() {
     // Load or create default config
     let config = config::create_shared_config(Some("config.toml"));
 
-    // Create a map using config values
-    let map = {
-        let cfg = config.read();
-        let terrain_config = cfg.terrain_config.clone();
-        generate_map(
-            terrain_config.map_width,
-            terrain_config.map_height,
-            terrain_config
-        )
-    };
-
-    let server = Arc::new(Server::new(map, config.clone()));
+    let server = Arc::new(Server::new(config, PathBuf::from(replay::REPLAY_DIR)));
 
     println!("Generals.io server (WS) starting on 0.0.0.0:1812/ws...");
 
-    // Start tick loop
-    let tick_server = server.clone();
-    let tick_config = config.clone();
-        tokio::spawn(async move {
-        let mut last_tick_ms = tick_config.read().tick_ms;
-        let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(last_tick_ms as u64));
-
-        loop {
-            interval.tick().await;
-
-            // Update interval if tick_ms has changed
-            let current_tick_ms = tick_config.read().tick_ms;
-
-            if (current_tick_ms - last_tick_ms).abs() > 0.001 {  // Use small epsilon for float comparison
-                println!("Updating tick rate from {last_tick_ms}ms to {current_tick_ms}ms");
-                interval = tokio::time::interval(tokio::time::Duration::from_millis(current_tick_ms as u64));
-                last_tick_ms = current_tick_ms;
-            }
-
-            tick_server.tick().await;
-        }
-    });
-
     let app = Router::new().route("/ws", get(move |ws| ws_handler(ws, server.clone())));
     let listener = tokio::net::TcpListener::bind("0.0.0.0:1812").await.unwrap();
     axum::serve(listener, app).await.unwrap();
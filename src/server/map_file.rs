@@ -0,0 +1,238 @@
+//! Hand-made maps, stored as TOML files in the `maps/` directory. See `generals::shared::map_file`
+//! for the format.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path as FsPath, PathBuf};
+
+use generals::shared::map_file::{tile_terrain, MapFile};
+use generals::shared::settings::SettingsBounds;
+use generals::shared::Terrain;
+
+use crate::map::{passable_regions, Cell};
+
+/// Directory map files are loaded from
+pub const MAP_DIR: &str = "maps";
+
+/// The tiles of a map file and where capitals spawn on it.
+#[derive(Debug, Clone)]
+pub struct CustomMap {
+    pub width: usize,
+    pub height: usize,
+    pub cells: Vec<Cell>,
+    pub spawns: Vec<usize>,
+}
+
+impl CustomMap {
+    /// Loads `maps/<name>.toml`.
+    pub fn load(name: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let contents = fs::read_to_string(map_path(name).ok_or("invalid map name")?)?;
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let file: MapFile = toml::from_str(contents)?;
+        Self::from_file(&file)
+    }
+
+    pub fn from_file(file: &MapFile) -> Result<Self, Box<dyn std::error::Error>> {
+        let height = file.rows.len();
+        let width = file.rows.first().map_or(0, |row| row.chars().count());
+        if width == 0 {
+            return Err("map has no tiles".into());
+        }
+
+        let mut cells = Vec::with_capacity(width * height);
+        let mut spawns = Vec::new();
+        for (y, row) in file.rows.iter().enumerate() {
+            if row.chars().count() != width {
+                return Err(format!("row {y} is {} tiles wide, expected {width}", row.chars().count()).into());
+            }
+            for (x, tile) in row.chars().enumerate() {
+                let Some((terrain, spawn)) = tile_terrain(tile) else {
+                    return Err(format!("unknown tile '{tile}' at {x},{y}").into());
+                };
+                if spawn {
+                    spawns.push(y * width + x);
+                }
+                let troops = if terrain == Terrain::City { file.city_troops } else { 0 };
+                cells.push(Cell { terrain, troops, owner_id: None });
+            }
+        }
+
+        for city in &file.cities {
+            let id = (city.x < width && city.y < height).then(|| city.y * width + city.x);
+            match id.map(|id| &mut cells[id]) {
+                Some(cell) if cell.terrain == Terrain::City => cell.troops = city.troops,
+                _ => return Err(format!("garrison at {},{} is not on a city", city.x, city.y).into()),
+            }
+        }
+
+        Ok(Self { width, height, cells, spawns })
+    }
+}
+
+/// Maps a map name to its file, rejecting anything that could escape the map directory.
+fn map_path(name: &str) -> Option<PathBuf> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_')) {
+        return None;
+    }
+    Some(FsPath::new(MAP_DIR).join(format!("{name}.toml")))
+}
+
+/// Checks that a map from the editor can be played, at a size hosts could also pick for generated maps.
+fn check_upload(file: &MapFile, bounds: &SettingsBounds) -> Result<(), String> {
+    let map = CustomMap::from_file(file).map_err(|e| e.to_string())?;
+    let sizes = bounds.min_map_size..=bounds.max_map_size;
+    if !sizes.contains(&map.width) || !sizes.contains(&map.height) {
+        return Err(format!("maps have to be {} to {} tiles wide and high", bounds.min_map_size, bounds.max_map_size));
+    }
+    if map.spawns.len() < 2 {
+        return Err("maps need at least two spawn points".to_string());
+    }
+    let regions = passable_regions(&map.cells, map.width, map.height);
+    if map.spawns.iter().any(|&spawn| regions[spawn] != regions[map.spawns[0]]) {
+        return Err("every spawn point has to be reachable from the others".to_string());
+    }
+    Ok(())
+}
+
+/// Saves a map from the editor as `maps/<name>.toml`. Existing maps are never overwritten, and nothing
+/// is saved once the map directory holds `max_maps` maps.
+pub fn save_upload(name: &str, file: &MapFile, bounds: &SettingsBounds, max_maps: usize) -> Result<(), String> {
+    let path = map_path(name).ok_or("map names may only use letters, digits, - and _")?;
+    check_upload(file, bounds)?;
+    if list_maps().len() >= max_maps {
+        return Err("the server has no room for more maps".to_string());
+    }
+
+    fs::create_dir_all(MAP_DIR).map_err(|e| format!("could not save the map: {e}"))?;
+    let mut out = fs::OpenOptions::new().write(true).create_new(true).open(&path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::AlreadyExists => format!("a map called {name} already exists"),
+        _ => format!("could not save the map: {e}"),
+    })?;
+    out.write_all(file.to_toml().as_bytes()).map_err(|e| format!("could not save the map: {e}"))
+}
+
+/// Names of the maps in the map directory, sorted.
+pub fn list_maps() -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(MAP_DIR)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| {
+                    let path = entry.path();
+                    if path.extension()? != "toml" {
+                        return None;
+                    }
+                    Some(path.file_stem()?.to_str()?.to_string())
+                })
+                .filter(|name| map_path(name).is_some())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    names.sort_unstable();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::sim::{GameSim, Rules};
+    use rand::{rngs::StdRng, SeedableRng};
+    use uuid::Uuid;
+
+    const SMALL_MAP: &str = r#"
+        city_troops = 30
+        rows = [
+            "@.#c",
+            "ds.@",
+        ]
+
+        [[cities]]
+        x = 3
+        y = 0
+        troops = 12
+    "#;
+
+    #[test]
+    fn map_files_are_parsed() {
+        let map = CustomMap::parse(SMALL_MAP).unwrap();
+        assert_eq!((map.width, map.height), (4, 2));
+        assert_eq!(map.spawns, vec![0, 7]);
+
+        let terrain: Vec<Terrain> = map.cells.iter().map(|cell| cell.terrain).collect();
+        assert_eq!(terrain, vec![
+            Terrain::Default, Terrain::Default, Terrain::Mountain, Terrain::City,
+            Terrain::Desert, Terrain::Swamp, Terrain::Default, Terrain::Default,
+        ]);
+        assert_eq!(map.cells[3].troops, 12);
+    }
+
+    #[test]
+    fn broken_map_files_are_rejected() {
+        assert!(CustomMap::parse(r#"rows = []"#).is_err());
+        assert!(CustomMap::parse(r#"rows = ["..", "..."]"#).is_err());
+        assert!(CustomMap::parse(r#"rows = ["..x"]"#).is_err());
+        assert!(CustomMap::parse("rows = [\"..\"]\n[[cities]]\nx = 1\ny = 0\ntroops = 5").is_err());
+        assert!(CustomMap::parse("rows = [\"c.\"]\n[[cities]]\nx = 2\ny = 0\ntroops = 5").is_err());
+    }
+
+    #[test]
+    fn capitals_spawn_on_the_spawn_slots() {
+        let map = CustomMap::parse(SMALL_MAP).unwrap();
+        let mut sim = GameSim::new(map.width, map.height, map.cells, Rules::from(&Config::default()));
+        sim.spawns = map.spawns;
+        let mut rng = StdRng::seed_from_u64(1);
+
+        let players = [Uuid::new_v4(), Uuid::new_v4()];
+        for player in players {
+            assert!(sim.place_capital(player, 4, &mut rng));
+        }
+        for spawn in [0, 7] {
+            assert_eq!(sim.cells[spawn].terrain, Terrain::Capital);
+            assert!(sim.cells[spawn].owner_id.is_some_and(|owner| players.contains(&owner)));
+        }
+    }
+
+    #[test]
+    fn uploads_have_to_be_playable() {
+        let bounds = SettingsBounds { min_map_size: 2, max_map_size: 4, ..SettingsBounds::default() };
+        let file = |rows: &[&str]| MapFile {
+            city_troops: 40,
+            rows: rows.iter().map(|row| row.to_string()).collect(),
+            cities: Vec::new(),
+        };
+
+        assert!(check_upload(&file(&["@.#", ".c@"]), &bounds).is_ok());
+        assert!(check_upload(&file(&["@.#", ".c."]), &bounds).is_err());
+        assert!(check_upload(&file(&["@...@"]), &bounds).is_err());
+        assert!(check_upload(&file(&["@.#", ".x@"]), &bounds).is_err());
+        // Spawns walled off from each other by mountains
+        assert!(check_upload(&file(&["@#.", "#.@"]), &bounds).is_err());
+        assert!(check_upload(&file(&["@.#@", "..#."]), &bounds).is_err());
+        assert!(check_upload(&file(&["@.#@", "...."]), &bounds).is_ok());
+        assert!(save_upload("../escape", &file(&["@.#", ".c@"]), &bounds, 100).is_err());
+    }
+
+    #[test]
+    fn uploads_stop_when_the_map_directory_is_full() {
+        let bounds = SettingsBounds { min_map_size: 2, max_map_size: 4, ..SettingsBounds::default() };
+        let file = MapFile { city_troops: 40, rows: vec!["@.#".to_string(), ".c@".to_string()], cities: Vec::new() };
+        let full = list_maps().len();
+        assert_eq!(save_upload("never-saved", &file, &bounds, full), Err("the server has no room for more maps".to_string()));
+        assert!(!list_maps().contains(&"never-saved".to_string()));
+    }
+
+    #[test]
+    fn bundled_maps_load() {
+        let names = list_maps();
+        assert!(!names.is_empty());
+        for name in names {
+            let map = CustomMap::load(&name).unwrap_or_else(|e| panic!("map {name} does not load: {e}"));
+            assert!(map.spawns.len() >= 2, "map {name} needs room for two players");
+        }
+    }
+}
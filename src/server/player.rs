@@ -1,12 +1,17 @@
-use std::{collections::HashMap, sync::Arc};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use axum::extract::ws::{WebSocket, Message};
 use futures_util::{stream::SplitSink, SinkExt};
 use tokio::sync::mpsc::{self, UnboundedSender};
 
-use generals::shared::{cb_packet::MapSync, game_state::GameState, path::Path, CBPacket, Color, PlayerView, SBPacket};
+use generals::shared::cb_packet::ChatRejectReason;
+use generals::shared::{player::BotDifficulty, Color, PlayerView, SBPacket};
 use uuid::Uuid;
 
-use crate::Server;
+use crate::config::Config;
+use crate::room::Room;
+use crate::sim::Input;
 
 use parking_lot::RwLock;
 
@@ -14,79 +19,240 @@ pub struct Player {
     pub id: Uuid,
     pub name: RwLock<String>,
     pub color: RwLock<Color>,
-    pub tx: UnboundedSender<Vec<u8>>,
-    pub paths: RwLock<HashMap<u32, Path>>,
-    pub alive: RwLock<bool>,
+    tx: RwLock<UnboundedSender<Vec<u8>>>,
+    pub spectator: RwLock<bool>,
+    // Spectators who asked to play in the next game
+    pub join_next_game: RwLock<bool>,
+    // Player whose perspective a spectator is watching, full vision if None
+    pub following: RwLock<Option<Uuid>>,
+    pub connected: RwLock<bool>,
+    // Incremented every time the player is rebound to a new websocket
+    connection: RwLock<u32>,
+    // Set for players controlled by the server
+    pub bot: Option<BotDifficulty>,
+    pub team: RwLock<Option<u8>>,
+    // Set in the lobby once the player is ready to start
+    pub ready: RwLock<bool>,
+    // When the player's recent chat messages were sent, for rate limiting
+    chat_times: RwLock<VecDeque<Instant>>,
 }
 
-impl Player {
-        pub fn new(id: Uuid, name: String, color: Color, mut sink: SplitSink<WebSocket, Message>) -> Self {
-        let (tx, mut rx) = mpsc::unbounded_channel();
+/// Spawns a dedicated task forwarding everything sent on the returned channel to the websocket.
+pub fn socket_sender(mut sink: SplitSink<WebSocket, Message>) -> UnboundedSender<Vec<u8>> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
 
-        // Spawn a dedicated task for handling this player's connection
-        tokio::spawn(async move {
-            while let Some(bytes) = rx.recv().await {
-                let _ = sink.send(Message::Binary(bytes)).await;
-            }
-        });
+    tokio::spawn(async move {
+        while let Some(bytes) = rx.recv().await {
+            let _ = sink.send(Message::Binary(bytes)).await;
+        }
+    });
 
+    tx
+}
+
+impl Player {
+    pub fn new(id: Uuid, name: String, color: Color, tx: UnboundedSender<Vec<u8>>) -> Self {
         Self {
             id,
             name: RwLock::new(name),
             color: RwLock::new(color),
-            tx,
-            paths: RwLock::new(HashMap::new()),
-            alive: RwLock::new(true),
+            tx: RwLock::new(tx),
+            spectator: RwLock::new(false),
+            join_next_game: RwLock::new(false),
+            following: RwLock::new(None),
+            connected: RwLock::new(true),
+            connection: RwLock::new(0),
+            bot: None,
+            team: RwLock::new(None),
+            ready: RwLock::new(false),
+            chat_times: RwLock::new(VecDeque::new()),
+        }
+    }
+
+    /// A player without a connection, e.g. for bots.
+    pub fn headless(id: Uuid, name: String, color: Color) -> Self {
+        let (tx, _) = mpsc::unbounded_channel();
+        Self::new(id, name, color, tx)
+    }
+
+    /// A server-controlled player, its moves are made by the room's bot strategies.
+    pub fn bot(id: Uuid, name: String, color: Color, difficulty: BotDifficulty) -> Self {
+        Self {
+            bot: Some(difficulty),
+            ..Self::headless(id, name, color)
         }
     }
 
     pub fn id(&self) -> Uuid { self.id }
 
-    pub async fn handle_packet(&self, packet: SBPacket, server: &Arc<Server>) {
+    pub fn connection(&self) -> u32 { *self.connection.read() }
+
+    /// Bots are always ready to play.
+    pub fn is_ready(&self) -> bool {
+        self.bot.is_some() || *self.ready.read()
+    }
+
+    /// Sends this player's traffic through `other`'s websocket and returns the new connection id.
+    pub fn rebind(&self, other: &Player) -> u32 {
+        *self.tx.write() = other.tx.read().clone();
+        *self.connected.write() = true;
+        let mut connection = self.connection.write();
+        *connection += 1;
+        *connection
+    }
+
+    /// Marks the player as disconnected if `connection` is still their current one.
+    pub fn disconnect(&self, connection: u32) -> bool {
+        if self.connection() != connection {
+            return false;
+        }
+        *self.connected.write() = false;
+        true
+    }
+
+    pub async fn handle_packet(&self, packet: SBPacket, room: &Arc<Room>) {
         match packet {
-            SBPacket::Login(_login) => {
-                println!("Received login packet from already logged in player {}", self.name.read());
+            SBPacket::Login(_) | SBPacket::Resume(_) | SBPacket::ListRooms | SBPacket::CreateRoom(_) | SBPacket::JoinRoom(_)
+            | SBPacket::LeaveRoom | SBPacket::ListReplays | SBPacket::WatchReplay(_) | SBPacket::ReplayControl(_) | SBPacket::Chat(_)
+            | SBPacket::KickPlayer(_) | SBPacket::UploadMap(_) => {
+                // Handled by the connection loop before a packet reaches the room
             }
             SBPacket::GiveMeMap => {
-                let map = server.map.to_map_view(self.id(), server);
-                let packet = CBPacket::MapSync(MapSync { map });
-                if let Ok(bytes) = bincode::serialize(&packet) {
-                    let _ = self.tx.send(bytes);
-                }
+                room.send_map_snapshot(self);
             }
             SBPacket::UpdatePaths(update_paths) => {
-                let mut paths = self.paths.write();
-                for (id, mut path) in update_paths.paths {
-                    // For existing paths, keep their valid_until value
-                    if let Some(existing_path) = paths.get(&id) {
-                        path.valid_until = existing_path.valid_until;
-                    } else {
-                        // New paths start at 0
-                        path.valid_until = 0;
-                    }
-                    paths.insert(id, path);
+                // Spectators have no armies to move
+                if *self.spectator.read() {
+                    return;
+                }
+
+                // Applied at the start of the next tick
+                let paths = room.validate_paths(self, update_paths.paths);
+                if !paths.is_empty() {
+                    room.queue_input(Input::UpdatePaths { player_id: self.id(), paths });
+                }
+            }
+            SBPacket::CancelPath(cancel) => {
+                if !*self.spectator.read() {
+                    room.queue_input(Input::CancelPath { player_id: self.id(), path_id: cancel.path_id });
+                }
+            }
+            SBPacket::ClearPaths => {
+                if !*self.spectator.read() {
+                    room.queue_input(Input::ClearPaths { player_id: self.id() });
+                }
+            }
+            SBPacket::UndoStep(undo) => {
+                if !*self.spectator.read() {
+                    room.queue_input(Input::UndoStep { player_id: self.id(), path_id: undo.path_id });
                 }
             }
             SBPacket::StartGame => {
-                server.set_game_state(GameState::InGame);
+                room.start_game(self);
             }
             SBPacket::PlayAgain => {
-                server.set_game_state(GameState::Lobby);
-                server.reset_map();
+                room.play_again(self);
+            }
+            SBPacket::SetReady(set_ready) => {
+                room.set_ready(self, set_ready.ready);
+            }
+            SBPacket::TransferHost(transfer) => {
+                room.transfer_host(self, transfer.player_id);
+            }
+            SBPacket::SetSettings(settings) => {
+                room.set_settings(self, settings);
+            }
+            SBPacket::SetSpectator(set_spectator) => {
+                room.set_spectator(self, set_spectator.spectator);
+            }
+            SBPacket::SetTeam(set_team) => {
+                room.set_team(self, set_team.team);
+            }
+            SBPacket::AddBot(add_bot) => {
+                room.add_bot(self, add_bot.difficulty);
+            }
+            SBPacket::RemoveBots => {
+                room.remove_bots(self);
+            }
+            SBPacket::FollowPlayer(follow) => {
+                if *self.spectator.read() {
+                    *self.following.write() = follow.player_id;
+                    room.send_map_snapshot(self);
+                }
             }
         }
     }
 
+    /// Cleans up a chat message and checks it against the config's limits. Returns the text to pass on,
+    /// which only counts towards the player's rate limit once it is recorded with `record_chat`.
+    pub fn check_chat(&self, text: &str, config: &Config, now: Instant) -> Result<String, ChatRejectReason> {
+        let text: String = text.chars().filter(|c| !c.is_control()).collect();
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(ChatRejectReason::Empty);
+        }
+        if text.chars().count() > config.max_chat_length {
+            return Err(ChatRejectReason::TooLong);
+        }
+
+        let window = Duration::from_secs(config.chat_rate_window_secs);
+        let mut chat_times = self.chat_times.write();
+        while chat_times.front().is_some_and(|&sent| now.duration_since(sent) >= window) {
+            chat_times.pop_front();
+        }
+        if chat_times.len() >= config.chat_rate_limit {
+            return Err(ChatRejectReason::RateLimited);
+        }
+
+        Ok(text.to_string())
+    }
+
+    /// Counts a delivered chat message towards the player's rate limit.
+    pub fn record_chat(&self, sent: Instant) {
+        self.chat_times.write().push_back(sent);
+    }
+
     pub fn send_bytes(&self, bytes: Vec<u8>) {
-        let _ = self.tx.send(bytes);
+        let _ = self.tx.read().send(bytes);
     }
 
-    pub fn to_view(&self) -> PlayerView {
+    /// Whether the player is still in the game is up to the room's simulation.
+    pub fn to_view(&self, alive: bool) -> PlayerView {
         PlayerView {
             id: self.id,
             name: self.name.read().clone(),
             color: *self.color.read(),
-            alive: *self.alive.read(),
+            alive,
+            spectator: *self.spectator.read(),
+            connected: *self.connected.read(),
+            bot: self.bot,
+            team: *self.team.read(),
+            ready: self.is_ready(),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chat_messages_are_cleaned_up_and_limited() {
+        let player = Player::headless(Uuid::new_v4(), "Alice".to_string(), Color { r: 0, g: 0, b: 0, a: 255 });
+        let config = Config { max_chat_length: 10, chat_rate_limit: 2, chat_rate_window_secs: 10, ..Config::default() };
+        let start = Instant::now();
+
+        assert_eq!(player.check_chat("  \n ", &config, start), Err(ChatRejectReason::Empty));
+        assert_eq!(player.check_chat("far too long", &config, start), Err(ChatRejectReason::TooLong));
+        assert_eq!(player.check_chat(" gg\u{7}wp ", &config, start), Ok("ggwp".to_string()));
+        player.record_chat(start);
+        // Messages that passed the checks but reached nobody are never recorded and do not count
+        assert_eq!(player.check_chat("anyone?", &config, start), Ok("anyone?".to_string()));
+        assert_eq!(player.check_chat("again", &config, start), Ok("again".to_string()));
+        player.record_chat(start);
+
+        // Rejected messages do not count, the third one within the window does not get through
+        assert_eq!(player.check_chat("spam", &config, start + Duration::from_secs(9)), Err(ChatRejectReason::RateLimited));
+        assert_eq!(player.check_chat("later", &config, start + Duration::from_secs(10)), Ok("later".to_string()));
+    }
+}
@@ -0,0 +1,482 @@
+use std::fs;
+use std::path::{Path as FsPath, PathBuf};
+use std::sync::{Arc, Weak};
+
+use generals::shared::cb_packet::{ReplayFrame, ReplayList, ReplayStarted};
+use generals::shared::player::BotDifficulty;
+use generals::shared::sb_packet::ReplayControl;
+use generals::shared::{CBPacket, Color, PlayerView};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::map::{self, Cell};
+use crate::player::Player;
+use crate::room::Room;
+use crate::sim::{GameSim, Input, Rules};
+
+/// Directory the server writes replays to and loads them from
+pub const REPLAY_DIR: &str = "replays";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayPlayer {
+    pub id: Uuid,
+    pub name: String,
+    pub color: Color,
+    pub bot: Option<BotDifficulty>,
+    pub team: Option<u8>,
+}
+
+/// Everything needed to re-simulate a game: its starting state and every input in tick order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub room: String,
+    pub config: Config,
+    pub seed: u64,
+    pub width: usize,
+    pub height: usize,
+    pub start_tick: u32,
+    pub initial_cells: Vec<Cell>,
+    pub players: Vec<ReplayPlayer>,
+    // Inputs tagged with the number of ticks that had passed when they were applied
+    pub events: Vec<(u32, Input)>,
+    pub length: u32,
+}
+
+impl Replay {
+    pub fn load(dir: &FsPath, name: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let bytes = fs::read(replay_path(dir, name).ok_or("invalid replay name")?)?;
+        Ok(bincode::deserialize(&bytes)?)
+    }
+
+    pub fn save(&self, dir: &FsPath, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        fs::create_dir_all(dir)?;
+        let bytes = bincode::serialize(self)?;
+        fs::write(replay_path(dir, name).ok_or("invalid replay name")?, bytes)?;
+        Ok(())
+    }
+
+    /// The game as it was before its first tick.
+    pub fn start(&self) -> GameSim {
+        let mut sim = GameSim::new(self.width, self.height, self.initial_cells.clone(), Rules::from(&self.config));
+        for player in &self.players {
+            sim.add_player(player.id, player.team);
+        }
+        sim.tick = self.start_tick;
+        sim
+    }
+
+    /// Inputs that were applied on the given tick of the replay.
+    pub fn inputs_at(&self, tick: u32) -> Vec<Input> {
+        let first = self.events.partition_point(|(event_tick, _)| *event_tick < tick);
+        self.events[first..].iter()
+            .take_while(|(event_tick, _)| *event_tick == tick)
+            .map(|(_, input)| input.clone())
+            .collect()
+    }
+}
+
+/// Ticks between the snapshots a playback keeps
+const KEYFRAME_INTERVAL: u32 = 50;
+
+/// A replay being re-simulated. Only a snapshot every `KEYFRAME_INTERVAL` ticks is kept,
+/// other ticks are reached by simulating forward from the closest snapshot before them.
+pub struct Playback {
+    replay: Replay,
+    keyframes: Vec<GameSim>,
+    sim: GameSim,
+    // Ticks `sim` has been advanced by
+    position: u32,
+}
+
+impl Playback {
+    /// Runs through the whole game once to take the snapshots.
+    pub fn new(replay: Replay) -> Self {
+        let start = replay.start();
+        let mut keyframes = vec![start.clone()];
+        let mut sim = start.clone();
+        for tick in 0..replay.length {
+            sim.step(replay.inputs_at(tick));
+            if (tick + 1).is_multiple_of(KEYFRAME_INTERVAL) {
+                keyframes.push(sim.clone());
+            }
+        }
+
+        Self { replay, keyframes, sim: start, position: 0 }
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    /// Map state after `tick` ticks, capped at the end of the game.
+    pub fn cells_at(&mut self, tick: u32) -> &[Cell] {
+        let tick = tick.min(self.replay.length);
+        let keyframe = tick / KEYFRAME_INTERVAL;
+        // Going back, or past the next snapshot, starts over from the closest snapshot
+        if tick < self.position || keyframe > self.position / KEYFRAME_INTERVAL {
+            self.sim = self.keyframes[keyframe as usize].clone();
+            self.position = keyframe * KEYFRAME_INTERVAL;
+        }
+        while self.position < tick {
+            self.sim.step(self.replay.inputs_at(self.position));
+            self.position += 1;
+        }
+        &self.sim.cells
+    }
+}
+
+/// Maps a replay name to its file, rejecting anything that could escape the replay directory.
+fn replay_path(dir: &FsPath, name: &str) -> Option<PathBuf> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_')) {
+        return None;
+    }
+    Some(dir.join(format!("{name}.replay")))
+}
+
+pub fn list_replays(dir: &FsPath) -> CBPacket {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| {
+                    let path = entry.path();
+                    if path.extension()? != "replay" {
+                        return None;
+                    }
+                    Some(path.file_stem()?.to_str()?.to_string())
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // Newest first, names start with the time the game ended
+    names.sort_unstable_by(|a, b| b.cmp(a));
+    CBPacket::ReplayList(ReplayList { names })
+}
+
+/// Collects a game's inputs while it is being played.
+pub struct ReplayRecorder {
+    replay: Replay,
+    dir: PathBuf,  // Where the replay is written once the game ends
+}
+
+impl ReplayRecorder {
+    pub fn start(room: &Room, dir: PathBuf) -> Self {
+        let sim = room.sim.read();
+        let players = room.players.read()
+            .values()
+            .filter(|player| !*player.spectator.read())
+            .map(|player| ReplayPlayer {
+                id: player.id(),
+                name: player.name.read().clone(),
+                color: *player.color.read(),
+                bot: player.bot,
+                team: *player.team.read(),
+            })
+            .collect();
+
+        Self {
+            replay: Replay {
+                room: room.name.clone(),
+                config: room.config.read().clone(),
+                seed: *room.seed.read(),
+                width: sim.width,
+                height: sim.height,
+                start_tick: sim.tick,
+                initial_cells: sim.cells.clone(),
+                players,
+                events: Vec::new(),
+                length: 0,
+            },
+            dir,
+        }
+    }
+
+    pub fn record(&mut self, input: Input) {
+        self.replay.events.push((self.replay.length, input));
+    }
+
+    pub fn record_tick(&mut self) {
+        self.replay.length += 1;
+    }
+
+    /// Writes the replay to disk in the background.
+    pub fn finish(self) {
+        let Self { replay, dir } = self;
+        if replay.length == 0 {
+            return;
+        }
+
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        let room_name: String = replay.room.chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let name = format!("{timestamp}-{room_name}");
+
+        tokio::task::spawn_blocking(move || {
+            match replay.save(&dir, &name) {
+                Ok(()) => println!("Saved replay {name}"),
+                Err(e) => eprintln!("Failed to save replay {name}: {e}"),
+            }
+        });
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PlaybackState {
+    position: usize,
+    playing: bool,
+    speed: f32,
+    perspective: Option<Uuid>,
+}
+
+/// Plays a re-simulated replay back to a single player.
+pub struct ReplayViewer {
+    playback: RwLock<Playback>,
+    // Number of frames: the start of the game and one after every tick
+    frame_count: usize,
+    width: usize,
+    height: usize,
+    config: Config,
+    players: Vec<ReplayPlayer>,
+    player: Arc<Player>,
+    state: RwLock<PlaybackState>,
+}
+
+impl ReplayViewer {
+    pub async fn open(dir: &FsPath, name: &str, player: Arc<Player>) -> Result<Arc<Self>, Box<dyn std::error::Error>> {
+        // Loading and re-simulating a whole game takes a while, keep it off the async workers
+        let (dir, file_name) = (dir.to_path_buf(), name.to_string());
+        let playback = tokio::task::spawn_blocking(move || {
+            Replay::load(&dir, &file_name).map(Playback::new).map_err(|e| e.to_string())
+        }).await??;
+        let replay = playback.replay();
+
+        let players = replay.players.iter()
+            .map(|player| PlayerView {
+                id: player.id,
+                name: player.name.clone(),
+                color: player.color,
+                alive: true,
+                spectator: false,
+                connected: true,
+                bot: player.bot,
+                team: player.team,
+                ready: true,
+            })
+            .collect();
+        let started = CBPacket::ReplayStarted(ReplayStarted {
+            name: name.to_string(),
+            seed: replay.seed,
+            total_ticks: replay.length,
+            players,
+        });
+
+        let viewer = Arc::new(Self {
+            frame_count: replay.length as usize + 1,
+            width: replay.width,
+            height: replay.height,
+            config: replay.config.clone(),
+            players: replay.players.clone(),
+            playback: RwLock::new(playback),
+            player,
+            state: RwLock::new(PlaybackState {
+                position: 0,
+                playing: true,
+                speed: 1.0,
+                perspective: None,
+            }),
+        });
+
+        if let Ok(bytes) = bincode::serialize(&started) {
+            viewer.player.send_bytes(bytes);
+        }
+        viewer.send_frame();
+        spawn_playback_loop(&viewer);
+
+        Ok(viewer)
+    }
+
+    pub fn control(&self, control: ReplayControl) {
+        {
+            let mut state = self.state.write();
+            match control {
+                ReplayControl::Play => {
+                    // Restart from the beginning once the end was reached
+                    if state.position + 1 >= self.frame_count {
+                        state.position = 0;
+                    }
+                    state.playing = true;
+                }
+                ReplayControl::Pause => state.playing = false,
+                ReplayControl::Seek(tick) => state.position = (tick as usize).min(self.frame_count - 1),
+                // NaN would slip through the clamp and spin the playback loop
+                ReplayControl::SetSpeed(speed) if speed.is_finite() => state.speed = speed.clamp(0.25, 16.0),
+                ReplayControl::SetSpeed(_) => {}
+                ReplayControl::Perspective(perspective) => state.perspective = perspective,
+                ReplayControl::Stop => state.playing = false,
+            }
+        }
+        self.send_frame();
+    }
+
+    fn send_frame(&self) {
+        let state = self.state.read().clone();
+        // A player's perspective includes what their teammates saw
+        let vision: Option<Vec<Uuid>> = state.perspective.map(|perspective| {
+            let team = self.players.iter().find(|player| player.id == perspective).and_then(|player| player.team);
+            let mut vision: Vec<Uuid> = self.players.iter()
+                .filter(|player| team.is_some() && player.team == team)
+                .map(|player| player.id)
+                .collect();
+            if !vision.contains(&perspective) {
+                vision.push(perspective);
+            }
+            vision
+        });
+        let mut playback = self.playback.write();
+        let cells = playback.cells_at(state.position as u32);
+        let map = map::view_cells(cells, self.width, self.height, vision.as_deref(), &self.config);
+        drop(playback);
+        let frame = CBPacket::ReplayFrame(ReplayFrame {
+            tick: state.position as u32,
+            playing: state.playing,
+            speed: state.speed,
+            map,
+        });
+        if let Ok(bytes) = bincode::serialize(&frame) {
+            self.player.send_bytes(bytes);
+        }
+    }
+}
+
+/// Advances playback until the viewer is dropped.
+fn spawn_playback_loop(viewer: &Arc<ReplayViewer>) {
+    let viewer: Weak<ReplayViewer> = Arc::downgrade(viewer);
+    tokio::spawn(async move {
+        while let Some(delay_ms) = viewer.upgrade().map(|viewer| {
+            viewer.config.tick_ms / viewer.state.read().speed as f64
+        }) {
+            tokio::time::sleep(tokio::time::Duration::from_millis(delay_ms as u64)).await;
+
+            let Some(viewer) = viewer.upgrade() else {
+                break;
+            };
+
+            let advanced = {
+                let mut state = viewer.state.write();
+                if state.playing && state.position + 1 < viewer.frame_count {
+                    state.position += 1;
+                    // Stop at the last frame
+                    state.playing = state.position + 1 < viewer.frame_count;
+                    true
+                } else {
+                    false
+                }
+            };
+            if advanced {
+                viewer.send_frame();
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use generals::shared::path::Path;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    // Plays a game on an 8x8 map, recording it the way a room does
+    fn recorded_game(ticks: u32) -> (Replay, GameSim) {
+        let config = Config::default();
+        let mut sim = GameSim::new(8, 8, vec![Cell::default(); 64], Rules::from(&config));
+        let mut rng = StdRng::seed_from_u64(3);
+        let players = [Uuid::from_u128(1), Uuid::from_u128(2)];
+        for &player_id in &players {
+            sim.add_player(player_id, None);
+            sim.place_capital(player_id, 4, &mut rng);
+        }
+
+        let mut replay = Replay {
+            room: "test".to_string(),
+            config,
+            seed: 3,
+            width: 8,
+            height: 8,
+            start_tick: sim.tick,
+            initial_cells: sim.cells.clone(),
+            players: players.iter()
+                .map(|&id| ReplayPlayer { id, name: id.to_string(), color: Color { r: 0, g: 0, b: 0, a: 255 }, bot: None, team: None })
+                .collect(),
+            events: Vec::new(),
+            length: 0,
+        };
+
+        let capital = |sim: &GameSim, player_id| sim.cells.iter().position(|cell| cell.owner_id == Some(player_id)).unwrap() as u32;
+        for tick in 0..ticks {
+            // Every 30 ticks both players send their army one tile down and back
+            let inputs: Vec<Input> = if tick % 30 == 10 {
+                players.iter()
+                    .map(|&player_id| {
+                        let from = capital(&sim, player_id);
+                        let next = if from + 8 < 64 { from + 8 } else { from - 8 };
+                        Input::UpdatePaths { player_id, paths: HashMap::from([(tick, Path::new(vec![from, next, from]))]) }
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            replay.events.extend(inputs.iter().map(|input| (tick, input.clone())));
+            replay.length += 1;
+            sim.step(inputs);
+        }
+        (replay, sim)
+    }
+
+    #[test]
+    fn playback_reproduces_the_recorded_game() {
+        let (replay, sim) = recorded_game(180);
+        let mut playback = Playback::new(replay);
+        assert_eq!(playback.cells_at(180), sim.cells.as_slice());
+        // Past the end stays on the last frame
+        assert_eq!(playback.cells_at(500), sim.cells.as_slice());
+    }
+
+    #[test]
+    fn replays_are_saved_to_and_listed_from_their_directory() {
+        let dir = std::env::temp_dir().join(format!("generals-replays-{}", Uuid::new_v4()));
+        let (replay, sim) = recorded_game(60);
+        replay.save(&dir, "1-test").unwrap();
+
+        let CBPacket::ReplayList(list) = list_replays(&dir) else {
+            panic!("expected a replay list");
+        };
+        assert_eq!(list.names, vec!["1-test".to_string()]);
+        let loaded = Replay::load(&dir, "1-test").unwrap();
+        assert_eq!(Playback::new(loaded).cells_at(60), sim.cells.as_slice());
+        assert!(Replay::load(&dir, "../1-test").is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn seeking_matches_playing_straight_through() {
+        let (replay, _) = recorded_game(180);
+        let mut straight = Playback::new(replay.clone());
+        let frames: Vec<Vec<Cell>> = (0..=180).map(|tick| straight.cells_at(tick).to_vec()).collect();
+
+        let mut playback = Playback::new(replay);
+        for tick in [120, 3, 99, 100, 101, 49, 50, 180, 0, 151] {
+            assert_eq!(playback.cells_at(tick), frames[tick as usize].as_slice(), "tick {tick}");
+        }
+    }
+}
@@ -0,0 +1,1071 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Weak};
+
+use generals::shared::cb_packet::{ChatMessage, MapSync, MovementConfirmed, PathRejected, RoomJoined, RoomSettings, SyncPlayers};
+use generals::shared::game_state::GameState;
+use generals::shared::path::{Path, PathRejectReason};
+use generals::shared::player::{BotDifficulty, MAX_TEAMS};
+use generals::shared::sb_packet::ChatScope;
+use generals::shared::settings::GameSettings;
+use generals::shared::{CBPacket, Color, MapView, RoomView};
+use parking_lot::RwLock;
+use rand::{rngs::StdRng, SeedableRng};
+use uuid::Uuid;
+
+use crate::bot::Bot;
+use crate::config::{Config, SharedConfig};
+use crate::generator::generate_map_tiles;
+use crate::map::view_cells;
+use crate::map_file::{self, CustomMap};
+use crate::player::Player;
+use crate::replay::ReplayRecorder;
+use crate::sim::{Event, GameSim, Input, Rules};
+
+pub struct Room {
+    pub name: String,
+    pub players: RwLock<HashMap<Uuid, Arc<Player>>>,
+    // The game itself, everything else in the room is about getting it to and from the players
+    pub sim: RwLock<GameSim>,
+    pub game_state: RwLock<GameState>,
+    // The human who runs the lobby, passed on to whoever joined next when they leave
+    host: RwLock<Option<Uuid>>,
+    join_order: RwLock<Vec<Uuid>>,
+    // Players the host kicked, they cannot rejoin
+    kicked: RwLock<HashSet<Uuid>>,
+    // Snapshot of the server config taken when the room is created and on every reset,
+    // so hot reloads never change the rules of a game in progress
+    pub config: RwLock<Config>,
+    shared_config: SharedConfig,
+    // What the host changed in the lobby, applied on top of the server config at every reset
+    settings: RwLock<Option<GameSettings>>,
+    // Seed the current map was generated from, the RNG keeps going for capital placement
+    pub seed: RwLock<u64>,
+    rng: RwLock<StdRng>,
+    // Inputs waiting for the next tick
+    inputs: RwLock<Vec<Input>>,
+    recorder: RwLock<Option<ReplayRecorder>>,
+    replay_dir: Option<PathBuf>,
+    // Number of the last map update and the view each player was last sent,
+    // so that map updates only need to carry the cells that changed
+    sync_tick: RwLock<u32>,
+    sent_views: RwLock<HashMap<Uuid, MapView>>,
+    bots: RwLock<HashMap<Uuid, Bot>>,
+}
+
+/// Colors handed out to bots in the order they are added
+const BOT_COLORS: [Color; 6] = [
+    Color { r: 230, g: 80, b: 80, a: 255 },
+    Color { r: 80, g: 200, b: 120, a: 255 },
+    Color { r: 240, g: 200, b: 60, a: 255 },
+    Color { r: 170, g: 100, b: 230, a: 255 },
+    Color { r: 60, g: 200, b: 220, a: 255 },
+    Color { r: 240, g: 140, b: 50, a: 255 },
+];
+
+impl Room {
+    /// Games are recorded to `replay_dir`, or not at all without one.
+    pub fn new(name: String, shared_config: SharedConfig, replay_dir: Option<PathBuf>) -> Self {
+        let config = shared_config.read().clone();
+        let seed = config.terrain_config.seed.unwrap_or_else(rand::random);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let sim = new_sim(&config, &mut rng);
+
+        Self {
+            name,
+            players: RwLock::new(HashMap::new()),
+            sim: RwLock::new(sim),
+            game_state: RwLock::new(GameState::Lobby),
+            host: RwLock::new(None),
+            join_order: RwLock::new(Vec::new()),
+            kicked: RwLock::new(HashSet::new()),
+            config: RwLock::new(config),
+            shared_config,
+            settings: RwLock::new(None),
+            seed: RwLock::new(seed),
+            rng: RwLock::new(rng),
+            inputs: RwLock::new(Vec::new()),
+            recorder: RwLock::new(None),
+            replay_dir,
+            sync_tick: RwLock::new(0),
+            sent_views: RwLock::new(HashMap::new()),
+            bots: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn to_view(&self) -> RoomView {
+        let players = self.players.read();
+        let spectator_count = players.values().filter(|p| *p.spectator.read()).count();
+        RoomView {
+            name: self.name.clone(),
+            player_count: players.len() - spectator_count,
+            spectator_count,
+            game_state: *self.game_state.read(),
+        }
+    }
+
+    pub fn add_player(&self, player: Arc<Player>, spectate: bool) {
+        let player_id = player.id();
+
+        // Players joining a game in progress only get to watch until the next one
+        let in_game = *self.game_state.read() != GameState::Lobby;
+        *player.spectator.write() = spectate || in_game;
+        *player.join_next_game.write() = !spectate && in_game;
+        *player.following.write() = None;
+        *player.team.write() = None;
+        *player.ready.write() = false;
+        if !*player.spectator.read() {
+            self.add_to_game(&player);
+        }
+
+        // Whatever the player saw before is gone, they start from a fresh snapshot
+        self.sent_views.write().remove(&player_id);
+        self.players.write().insert(player_id, player.clone());
+        self.join_order.write().push(player_id);
+        println!("Player {} joined room {}", player.name.read(), self.name);
+        self.update_host();
+
+        self.sync_players();
+        self.sync_map();
+
+        // Send current game state to the new player
+        let state_packet = CBPacket::SetGameState(*self.game_state.read());
+        if let Ok(resp) = bincode::serialize(&state_packet) {
+            player.send_bytes(resp);
+        }
+        self.send_seed(&player);
+        self.send_settings(&player);
+    }
+
+    /// Tells a player which seed the current map was generated from.
+    pub fn send_seed(&self, player: &Player) {
+        if let Ok(resp) = bincode::serialize(&CBPacket::MapSeed(*self.seed.read())) {
+            player.send_bytes(resp);
+        }
+    }
+
+    /// Tells a player what the next game is played with.
+    pub fn send_settings(&self, player: &Player) {
+        let config = self.config.read();
+        let settings = CBPacket::RoomSettings(RoomSettings {
+            settings: config.settings(),
+            bounds: config.settings_bounds.clone(),
+            maps: map_file::list_maps(),
+        });
+        if let Ok(resp) = bincode::serialize(&settings) {
+            player.send_bytes(resp);
+        }
+    }
+
+    /// Changes the settings of the next game and rolls a new map with them.
+    /// Only the host can do this, in the lobby.
+    pub fn set_settings(&self, player: &Player, mut settings: GameSettings) {
+        if !self.is_host(player.id()) || *self.game_state.read() != GameState::Lobby {
+            return;
+        }
+
+        // Only maps that are actually there can be picked
+        if settings.map.as_ref().is_some_and(|map| !map_file::list_maps().contains(map)) {
+            settings.map = None;
+        }
+
+        *self.settings.write() = Some(settings);
+        println!("Player {} changed the settings of room {}", player.name.read(), self.name);
+        self.reset_map();
+    }
+
+    /// The server config with the host's settings on top.
+    fn game_config(&self) -> Config {
+        let mut config = self.shared_config.read().clone();
+        if let Some(settings) = self.settings.read().as_ref() {
+            config.apply_settings(settings);
+        }
+        config
+    }
+
+    /// Brings a player who reconnected back up to date.
+    pub fn resume_player(&self, player: &Player) {
+        let joined = CBPacket::RoomJoined(RoomJoined { name: self.name.clone() });
+        if let Ok(resp) = bincode::serialize(&joined) {
+            player.send_bytes(resp);
+        }
+
+        let state_packet = CBPacket::SetGameState(*self.game_state.read());
+        if let Ok(resp) = bincode::serialize(&state_packet) {
+            player.send_bytes(resp);
+        }
+        self.send_seed(player);
+        self.send_settings(player);
+
+        // Let everyone know the player is back
+        self.sync_players();
+
+        self.send_map_snapshot(player);
+    }
+
+    /// Moves a player between playing and spectating. Takes effect immediately in the lobby,
+    /// during a game spectators are only queued for the next one.
+    pub fn set_spectator(&self, player: &Player, spectator: bool) {
+        if *self.game_state.read() != GameState::Lobby {
+            if *player.spectator.read() {
+                *player.join_next_game.write() = !spectator;
+            }
+            return;
+        }
+
+        if *player.spectator.read() == spectator {
+            return;
+        }
+
+        *player.spectator.write() = spectator;
+        *player.join_next_game.write() = false;
+        *player.ready.write() = false;
+        *player.following.write() = None;
+        if spectator {
+            self.sim.write().remove_player(player.id());
+        } else {
+            self.add_to_game(player);
+        }
+
+        self.sync_players();
+        self.sync_map();
+    }
+
+    /// Puts a player on a team, or on their own with `None`. Only possible in the lobby.
+    pub fn set_team(&self, player: &Player, team: Option<u8>) {
+        if *self.game_state.read() != GameState::Lobby {
+            return;
+        }
+
+        let team = team.filter(|team| (1..=MAX_TEAMS).contains(team));
+        *player.team.write() = team;
+        self.sim.write().set_team(player.id(), team);
+        self.sync_players();
+    }
+
+    pub fn is_host(&self, player_id: Uuid) -> bool {
+        *self.host.read() == Some(player_id)
+    }
+
+    /// Makes sure the host is still in the room, otherwise the human who joined next takes over.
+    fn update_host(&self) {
+        let players = self.players.read();
+        let mut host = self.host.write();
+        if host.is_some_and(|host| players.contains_key(&host)) {
+            return;
+        }
+
+        *host = self.join_order.read().iter()
+            .copied()
+            .find(|id| players.get(id).is_some_and(|p| p.bot.is_none()));
+        if let Some(new_host) = host.and_then(|host| players.get(&host)) {
+            println!("Player {} is now the host of room {}", new_host.name.read(), self.name);
+        }
+    }
+
+    /// Hands the host role to another human in the room. Only the host can do this.
+    pub fn transfer_host(&self, player: &Player, target: Uuid) {
+        if !self.is_host(player.id()) {
+            return;
+        }
+        let Some(new_host) = self.players.read().get(&target).cloned() else {
+            return;
+        };
+        if new_host.bot.is_some() {
+            return;
+        }
+
+        *self.host.write() = Some(target);
+        println!("Player {} handed the host of room {} to {}", player.name.read(), self.name, new_host.name.read());
+        self.sync_players();
+    }
+
+    /// Marks a player as ready to start. Only possible for players in the lobby.
+    pub fn set_ready(&self, player: &Player, ready: bool) {
+        if *self.game_state.read() != GameState::Lobby || *player.spectator.read() {
+            return;
+        }
+
+        *player.ready.write() = ready;
+        self.sync_players();
+    }
+
+    /// Starts the game if `player` is the host and enough players are ready.
+    pub fn start_game(&self, player: &Player) {
+        if !self.is_host(player.id()) || *self.game_state.read() != GameState::Lobby {
+            return;
+        }
+
+        let ready = self.players.read().values()
+            .filter(|p| !*p.spectator.read() && p.is_ready())
+            .count();
+        let needed = self.config.read().min_ready_players;
+        if ready < needed {
+            println!("Room {} cannot start yet, {ready} of {needed} players are ready", self.name);
+            return;
+        }
+
+        // The config may have been reloaded with another map or map size since the map was made
+        let map_source = |config: &Config| (config.map.clone(), config.terrain_config.map_width, config.terrain_config.map_height);
+        let next_source = map_source(&self.game_config());
+        if next_source != map_source(&self.config.read()) {
+            println!("Map of room {} changed, making a new one", self.name);
+            self.reset_map();
+        }
+
+        self.set_game_state(GameState::InGame);
+    }
+
+    /// Goes back to the lobby with a new map once the game is over. Only the host can do this.
+    pub fn play_again(&self, player: &Player) {
+        if !self.is_host(player.id()) || *self.game_state.read() != GameState::GameOver {
+            return;
+        }
+
+        self.set_game_state(GameState::Lobby);
+        self.reset_map();
+    }
+
+    /// Lets the host remove `target` from the room for good. Bots are removed right away,
+    /// returns true if `target` is a human who still has to be taken out of the room.
+    pub fn kick(&self, player: &Player, target: Uuid) -> bool {
+        if !self.is_host(player.id()) || target == player.id() {
+            return false;
+        }
+        let Some(kicked) = self.players.read().get(&target).cloned() else {
+            return false;
+        };
+        println!("Player {} was kicked from room {}", kicked.name.read(), self.name);
+
+        if kicked.bot.is_some() {
+            self.bots.write().remove(&target);
+            self.remove_player(target);
+            return false;
+        }
+
+        self.kicked.write().insert(target);
+        true
+    }
+
+    pub fn is_kicked(&self, player_id: Uuid) -> bool {
+        self.kicked.read().contains(&player_id)
+    }
+
+    /// Passes a chat message on to everyone in `scope`. Returns false if nobody could receive it.
+    pub fn send_chat(&self, sender: &Player, scope: ChatScope, text: String) -> bool {
+        let spectator = *sender.spectator.read();
+        let team = *sender.team.read();
+        let players = self.players.read();
+        let recipients: Vec<&Arc<Player>> = match scope {
+            ChatScope::Lobby => return false,
+            ChatScope::All => players.values().collect(),
+            ChatScope::Team if spectator => players.values().filter(|p| *p.spectator.read()).collect(),
+            ChatScope::Team => {
+                let Some(team) = team else {
+                    return false;
+                };
+                players.values().filter(|p| !*p.spectator.read() && *p.team.read() == Some(team)).collect()
+            }
+        };
+
+        let message = CBPacket::ChatMessage(ChatMessage {
+            scope,
+            sender: sender.id(),
+            name: sender.name.read().clone(),
+            text,
+        });
+        if let Ok(bytes) = bincode::serialize(&message) {
+            for player in recipients {
+                player.send_bytes(bytes.clone());
+            }
+        }
+        true
+    }
+
+    /// Adds a server-controlled player. Only the host can do this, and only in the lobby.
+    pub fn add_bot(&self, player: &Player, difficulty: BotDifficulty) {
+        if !self.is_host(player.id()) || *self.game_state.read() != GameState::Lobby {
+            return;
+        }
+
+        let mut bots = self.bots.write();
+        let kind = match difficulty {
+            BotDifficulty::Expansion => "Expansion",
+            BotDifficulty::Aggressive => "Aggressive",
+        };
+        let name = format!("{kind} Bot {}", bots.len() + 1);
+        let color = BOT_COLORS[bots.len() % BOT_COLORS.len()];
+        let player = Arc::new(Player::bot(Uuid::new_v4(), name, color, difficulty));
+        bots.insert(player.id(), Bot::new(difficulty));
+        drop(bots);
+
+        self.add_player(player, false);
+    }
+
+    /// Removes every bot from the room. Only the host can do this, and only in the lobby.
+    pub fn remove_bots(&self, player: &Player) {
+        if !self.is_host(player.id()) || *self.game_state.read() != GameState::Lobby {
+            return;
+        }
+
+        let bot_ids: Vec<Uuid> = self.bots.write().drain().map(|(id, _)| id).collect();
+        for bot_id in bot_ids {
+            self.remove_player(bot_id);
+        }
+    }
+
+    /// Lets every living bot look at its view of the map and queue its next move,
+    /// which then goes through the same input queue as a human's paths.
+    pub fn run_bots(&self) {
+        let mut bots = self.bots.write();
+        let mut bot_ids: Vec<Uuid> = bots.keys().copied().collect();
+        bot_ids.sort_unstable();
+
+        for bot_id in bot_ids {
+            let Some(player) = self.players.read().get(&bot_id).cloned() else {
+                continue;
+            };
+            let Some(paths) = self.sim.read().players.get(&bot_id)
+                .filter(|sim_player| sim_player.alive)
+                .map(|sim_player| sim_player.paths.clone()) else {
+                continue;
+            };
+
+            let view = self.map_view(bot_id);
+            let allies = self.sim.read().vision_of(bot_id);
+            let max_path_length = self.config.read().max_path_length;
+            if let Some(bot) = bots.get_mut(&bot_id)
+                && let Some(paths) = bot.think(&view, bot_id, &allies, &paths, max_path_length) {
+                let paths = self.validate_paths(&player, paths);
+                if paths.is_empty() {
+                    continue;
+                }
+                // Bots only start a path once the last one is done, so the old ones can go
+                self.queue_input(Input::ClearPaths { player_id: bot_id });
+                self.queue_input(Input::UpdatePaths { player_id: bot_id, paths });
+            }
+        }
+    }
+
+    /// Gives the player a capital on the map. Players who would spawn too close to someone else
+    /// have to spectate instead, so the minimum distance between capitals always holds.
+    fn add_to_game(&self, player: &Player) {
+        let min_distance = self.config.read().terrain_config.min_capital_distance;
+        let mut sim = self.sim.write();
+        sim.add_player(player.id(), *player.team.read());
+        if !sim.place_capital(player.id(), min_distance, &mut *self.rng.write()) {
+            sim.remove_player(player.id());
+            *player.spectator.write() = true;
+            *player.ready.write() = false;
+            println!("No room for another capital in room {}, player {} spectates", self.name, player.name.read());
+        }
+    }
+
+    /// Drops every path the player is not allowed to make and tells them why.
+    /// Returns the paths that passed.
+    pub fn validate_paths(&self, player: &Player, paths: HashMap<u32, Path>) -> HashMap<u32, Path> {
+        let (max_paths, max_path_length) = {
+            let config = self.config.read();
+            (config.max_paths, config.max_path_length)
+        };
+        let sim = self.sim.read();
+        let Some(existing) = sim.players.get(&player.id()).map(|sim_player| &sim_player.paths) else {
+            return HashMap::new();
+        };
+
+        // Paths still being walked count against the limit unless this update replaces them
+        let mut active = existing.iter()
+            .filter(|(id, path)| !paths.contains_key(id) && path.valid_until as usize + 1 < path.tile_ids.len())
+            .count();
+
+        let mut paths: Vec<(u32, Path)> = paths.into_iter().collect();
+        paths.sort_unstable_by_key(|(path_id, _)| *path_id);
+
+        let mut accepted = HashMap::new();
+        for (path_id, path) in paths {
+            let result = path.validate(sim.width, sim.height, max_path_length).and_then(|()| {
+                if active >= max_paths {
+                    return Err(PathRejectReason::TooManyPaths);
+                }
+                // Paths already accepted were checked when they started, unless the update moves their start
+                let start = path.tile_ids[0];
+                let same_start = existing.get(&path_id).is_some_and(|old| old.tile_ids.first() == Some(&start));
+                if !same_start && sim.cells[start as usize].owner_id != Some(player.id()) {
+                    return Err(PathRejectReason::NotOwned);
+                }
+                Ok(())
+            });
+
+            match result {
+                Ok(()) => {
+                    active += 1;
+                    accepted.insert(path_id, path);
+                }
+                Err(reason) => {
+                    let rejected = CBPacket::PathRejected(PathRejected { path_id, reason });
+                    if let Ok(bytes) = bincode::serialize(&rejected) {
+                        player.send_bytes(bytes);
+                    }
+                }
+            }
+        }
+
+        accepted
+    }
+
+    pub fn queue_input(&self, input: Input) {
+        self.inputs.write().push(input);
+    }
+
+    /// Takes the queued inputs, recording them if a replay is being made.
+    pub fn take_inputs(&self) -> Vec<Input> {
+        let inputs = std::mem::take(&mut *self.inputs.write());
+        if let Some(recorder) = self.recorder.write().as_mut() {
+            for input in &inputs {
+                recorder.record(input.clone());
+            }
+        }
+        inputs
+    }
+
+    /// Applies the queued inputs outside of a tick, e.g. departures after the game ended.
+    pub fn apply_inputs(&self) {
+        let inputs = self.take_inputs();
+        let mut events = Vec::new();
+        {
+            let mut sim = self.sim.write();
+            for input in inputs {
+                sim.apply_input(input, &mut events);
+            }
+        }
+        self.handle_events(events);
+    }
+
+    /// Passes what happened in the game on to the players.
+    pub fn handle_events(&self, events: Vec<Event>) {
+        let mut players_changed = false;
+
+        for event in events {
+            match event {
+                Event::MovementConfirmed { player_id, path_id, valid_until } => {
+                    let confirmed = CBPacket::MovementConfirmed(MovementConfirmed { path_id, valid_until });
+                    if let Some(player) = self.players.read().get(&player_id)
+                        && let Ok(bytes) = bincode::serialize(&confirmed) {
+                        player.send_bytes(bytes);
+                    }
+                }
+                Event::PlayerDefeated { .. } => {
+                    players_changed = true;
+                }
+                Event::PlayerLeft { player_id } => {
+                    self.forget_player(player_id);
+                    players_changed = true;
+                }
+                // Departures applied between games can look like a win, only a running game ends
+                Event::GameOver { .. } if *self.game_state.read() == GameState::InGame => {
+                    self.set_game_state(GameState::GameOver);
+                }
+                Event::GameOver { .. } => {}
+            }
+        }
+
+        if players_changed {
+            self.sync_players();
+        }
+    }
+
+    pub fn remove_player(&self, player_id: Uuid) {
+        // During a game the departure happens on the next tick, so replays see it at the same moment
+        if *self.game_state.read() == GameState::InGame {
+            self.queue_input(Input::Leave { player_id });
+            return;
+        }
+
+        // Remove player from the map
+        self.sim.write().remove_player(player_id);
+
+        // Remove player from the players list
+        self.forget_player(player_id);
+
+        // Notify remaining players about the player list change
+        self.sync_players();
+
+        // Sync map to show territory changes
+        self.sync_map();
+    }
+
+    fn forget_player(&self, player_id: Uuid) {
+        self.players.write().remove(&player_id);
+        self.join_order.write().retain(|&id| id != player_id);
+        self.update_host();
+    }
+
+    /// True once every human player has left, including those whose departure is still queued.
+    /// Bots never keep a room open.
+    pub fn is_empty(&self) -> bool {
+        let leaving = self.inputs.read().iter()
+            .filter(|input| matches!(input, Input::Leave { .. }))
+            .count();
+        let humans = self.players.read().values().filter(|p| p.bot.is_none()).count();
+        humans <= leaving
+    }
+
+    /// Called when the room is closed, saves the game played so far.
+    pub fn close(&self) {
+        if let Some(recorder) = self.recorder.write().take() {
+            recorder.finish();
+        }
+    }
+
+    pub fn record_tick(&self) {
+        if let Some(recorder) = self.recorder.write().as_mut() {
+            recorder.record_tick();
+        }
+    }
+
+    pub fn sync_players(&self) {
+        let sim = self.sim.read();
+        let all_players: Vec<_> = self.players.read().values().map(|p| p.to_view(sim.is_alive(p.id()))).collect();
+        drop(sim);
+        let sync = CBPacket::SyncPlayers(SyncPlayers {
+            players: all_players,
+            host: *self.host.read(),
+        });
+        if let Ok(resp) = bincode::serialize(&sync) {
+            // Send to all players in the room
+            for p in self.players.read().values() {
+                p.send_bytes(resp.clone());
+            }
+        }
+    }
+
+    /// What `player_id` can see of the map, including what their teammates see. Spectators following
+    /// a player see exactly what that player sees, other spectators and defeated players see everything.
+    pub fn map_view(&self, player_id: Uuid) -> MapView {
+        let sim = self.sim.read();
+        let config = self.config.read();
+
+        let mut viewer = Some(player_id);
+        if let Some(player) = self.players.read().get(&player_id) {
+            if *player.spectator.read() {
+                viewer = player.following.read().filter(|&followed| sim.players.contains_key(&followed));
+            } else if !sim.is_alive(player_id) {
+                viewer = None;
+            }
+        }
+
+        let vision = viewer.map(|viewer| sim.vision_of(viewer));
+        view_cells(&sim.cells, sim.width, sim.height, vision.as_deref(), &config)
+    }
+
+    /// Sends every player the cells that changed since their last update,
+    /// or a full snapshot if they have not been sent one yet.
+    pub fn sync_map(&self) {
+        let mut sent_views = self.sent_views.write();
+        let tick = {
+            let mut sync_tick = self.sync_tick.write();
+            *sync_tick += 1;
+            *sync_tick
+        };
+
+        let players = self.players.read();
+        sent_views.retain(|player_id, _| players.contains_key(player_id));
+        for player in players.values() {
+            let map_view = self.map_view(player.id());
+            let packet = match sent_views.get(&player.id()) {
+                Some(sent) if sent.width == map_view.width && sent.height == map_view.height => {
+                    CBPacket::MapDelta(sent.diff(&map_view, tick))
+                }
+                _ => CBPacket::MapSync(MapSync { map: map_view.clone(), tick }),
+            };
+
+            if let Ok(bytes) = bincode::serialize(&packet) {
+                player.send_bytes(bytes);
+            }
+            sent_views.insert(player.id(), map_view);
+        }
+    }
+
+    /// Sends a player the full map as they currently see it, e.g. when they ask to resync.
+    pub fn send_map_snapshot(&self, player: &Player) {
+        let mut sent_views = self.sent_views.write();
+        let tick = *self.sync_tick.read();
+        let map_view = self.map_view(player.id());
+        if let Ok(bytes) = bincode::serialize(&CBPacket::MapSync(MapSync { map: map_view.clone(), tick })) {
+            player.send_bytes(bytes);
+        }
+        sent_views.insert(player.id(), map_view);
+    }
+
+    pub fn set_game_state(&self, new_state: GameState) {
+        // Update room's game state
+        let old_state = std::mem::replace(&mut *self.game_state.write(), new_state);
+
+        // Record every game from its first tick until it ends
+        if old_state != GameState::InGame && new_state == GameState::InGame
+            && let Some(dir) = &self.replay_dir {
+            *self.recorder.write() = Some(ReplayRecorder::start(self, dir.clone()));
+        } else if new_state != GameState::InGame
+            && let Some(recorder) = self.recorder.write().take() {
+            recorder.finish();
+        }
+
+        // Create packet to notify clients
+        let packet = CBPacket::SetGameState(new_state);
+
+        // Serialize and send to all players
+        if let Ok(bytes) = bincode::serialize(&packet) {
+            for player in self.players.read().values() {
+                player.send_bytes(bytes.clone());
+            }
+        }
+    }
+
+    pub fn reset_map(&self) {
+        // Take a fresh config snapshot for the next game
+        let config = self.game_config();
+        let seed = config.terrain_config.seed;
+        *self.config.write() = config;
+
+        // Departures still queued from the last game happen now, path updates are dropped
+        self.inputs.write().retain(|input| matches!(input, Input::Leave { .. }));
+        self.apply_inputs();
+
+        // Every game gets a new seed unless the config pins one
+        let seed = seed.unwrap_or_else(rand::random);
+        let mut rng = StdRng::seed_from_u64(seed);
+        *self.seed.write() = seed;
+
+        // Start a new game on a fresh map, its size may have been changed by the host
+        let sim = new_sim(&self.config.read(), &mut rng);
+        *self.sim.write() = sim;
+        *self.rng.write() = rng;
+
+        // Reset player states and add capitals, in a fixed order so the seed reproduces the map
+        let mut players: Vec<_> = self.players.read().values().cloned().collect();
+        players.sort_by_key(|player| player.id());
+        for player in &players {
+            // Everyone readies up again for the next game
+            *player.ready.write() = false;
+
+            // Spectators who asked to play join now
+            if *player.join_next_game.read() {
+                *player.spectator.write() = false;
+                *player.join_next_game.write() = false;
+                *player.following.write() = None;
+            }
+
+            if !*player.spectator.read() {
+                self.add_to_game(player);
+            }
+        }
+
+        for player in &players {
+            self.send_seed(player);
+            self.send_settings(player);
+        }
+        self.sync_players();
+
+        // Sync the updated map to all players
+        self.sync_map();
+    }
+}
+
+/// Sets up the map for a new game: the configured map file, or a generated map if there is none.
+fn new_sim(config: &Config, rng: &mut StdRng) -> GameSim {
+    let rules = Rules::from(config);
+    if let Some(name) = &config.map {
+        match CustomMap::load(name) {
+            Ok(map) => {
+                let mut sim = GameSim::new(map.width, map.height, map.cells, rules);
+                sim.spawns = map.spawns;
+                return sim;
+            }
+            Err(e) => eprintln!("Failed to load map {name}: {e}, generating one instead"),
+        }
+    }
+
+    let terrain_config = &config.terrain_config;
+    let (width, height) = (terrain_config.map_width, terrain_config.map_height);
+    let cells = generate_map_tiles(width, height, terrain_config, rng);
+    GameSim::new(width, height, cells, rules)
+}
+
+/// Runs the room's game loop until the room is dropped.
+pub fn spawn_tick_loop(room: &Arc<Room>) {
+    let room: Weak<Room> = Arc::downgrade(room);
+    tokio::spawn(async move {
+        let Some(mut last_tick_ms) = room.upgrade().map(|room| room.config.read().tick_ms) else {
+            return;
+        };
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(last_tick_ms as u64));
+
+        loop {
+            interval.tick().await;
+
+            let Some(room) = room.upgrade() else {
+                break;
+            };
+
+            // Update interval if tick_ms has changed
+            let current_tick_ms = room.config.read().tick_ms;
+
+            if (current_tick_ms - last_tick_ms).abs() > 0.001 {  // Use small epsilon for float comparison
+                println!("Updating tick rate of room {} from {last_tick_ms}ms to {current_tick_ms}ms", room.name);
+                interval = tokio::time::interval(tokio::time::Duration::from_millis(current_tick_ms as u64));
+                last_tick_ms = current_tick_ms;
+            }
+
+            room.tick().await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use generals::shared::Terrain;
+
+    fn room(min_ready_players: usize) -> Room {
+        let config = Config { min_ready_players, ..Config::default() };
+        Room::new("test".to_string(), Arc::new(RwLock::new(config)), None)
+    }
+
+    fn human(name: &str) -> Arc<Player> {
+        Arc::new(Player::headless(Uuid::new_v4(), name.to_string(), Color { r: 0, g: 0, b: 0, a: 255 }))
+    }
+
+    #[test]
+    fn only_the_host_starts_once_enough_players_are_ready() {
+        let room = room(2);
+        let (alice, bob) = (human("Alice"), human("Bob"));
+        room.add_player(alice.clone(), false);
+        room.add_player(bob.clone(), false);
+        assert!(room.is_host(alice.id()));
+
+        room.set_ready(&alice, true);
+        room.start_game(&alice);
+        assert_eq!(*room.game_state.read(), GameState::Lobby);
+
+        room.set_ready(&bob, true);
+        room.start_game(&bob);
+        assert_eq!(*room.game_state.read(), GameState::Lobby);
+        room.start_game(&alice);
+        assert_eq!(*room.game_state.read(), GameState::InGame);
+
+        // Nobody gets to reset a game that is still running
+        room.play_again(&alice);
+        assert_eq!(*room.game_state.read(), GameState::InGame);
+    }
+
+    #[tokio::test]
+    async fn game_ends_when_everyone_else_leaves() {
+        let room = room(2);
+        let (alice, bob) = (human("Alice"), human("Bob"));
+        room.add_player(alice.clone(), false);
+        room.add_player(bob.clone(), false);
+        room.set_ready(&alice, true);
+        room.set_ready(&bob, true);
+        room.start_game(&alice);
+        assert_eq!(*room.game_state.read(), GameState::InGame);
+
+        room.remove_player(bob.id());
+        room.tick().await;
+        assert_eq!(*room.game_state.read(), GameState::GameOver);
+
+        room.play_again(&alice);
+        assert_eq!(*room.game_state.read(), GameState::Lobby);
+    }
+
+    #[test]
+    fn players_without_room_for_a_capital_spectate() {
+        let mut config = Config::default();
+        config.terrain_config.min_capital_distance = 100;
+        let room = Room::new("test".to_string(), Arc::new(RwLock::new(config)), None);
+        let (alice, bob) = (human("Alice"), human("Bob"));
+        room.add_player(alice.clone(), false);
+        room.add_player(bob.clone(), false);
+
+        // No tile is far enough from Alice's capital
+        assert!(!*alice.spectator.read());
+        assert!(*bob.spectator.read());
+        assert!(room.sim.read().is_alive(alice.id()));
+        assert!(!room.sim.read().players.contains_key(&bob.id()));
+
+        room.set_spectator(&bob, false);
+        assert!(*bob.spectator.read());
+    }
+
+    #[test]
+    fn reused_path_ids_still_have_to_start_on_an_owned_tile() {
+        let room = room(2);
+        let (alice, bob) = (human("Alice"), human("Bob"));
+        room.add_player(alice.clone(), false);
+        room.add_player(bob.clone(), false);
+        room.set_ready(&alice, true);
+        room.set_ready(&bob, true);
+        room.start_game(&alice);
+
+        let capital = |player: &Player| {
+            room.sim.read().cells.iter()
+                .position(|cell| cell.terrain == Terrain::Capital && cell.owner_id == Some(player.id()))
+                .unwrap() as u32
+        };
+        let (own, other) = (capital(&alice), capital(&bob));
+        let next = |tile: u32| {
+            let sim = room.sim.read();
+            crate::map::neighbors(tile as usize, sim.width, sim.height).next().unwrap() as u32
+        };
+        let submit = |tiles: Vec<u32>| {
+            let paths = room.validate_paths(&alice, HashMap::from([(0, Path::new(tiles))]));
+            let accepted = !paths.is_empty();
+            room.queue_input(Input::UpdatePaths { player_id: alice.id(), paths });
+            room.apply_inputs();
+            accepted
+        };
+
+        assert!(submit(vec![own, next(own)]));
+        // Extending the stored path is fine, moving its start onto Bob's capital is not
+        assert!(submit(vec![own, next(own), own]));
+        assert!(!submit(vec![other, next(other)]));
+        assert_eq!(room.sim.read().players[&alice.id()].paths[&0].tile_ids[0], own);
+    }
+
+    #[tokio::test]
+    async fn resumed_players_keep_their_army_and_paths() {
+        let room = room(2);
+        let (alice, bob) = (human("Alice"), human("Bob"));
+        room.add_player(alice.clone(), false);
+        room.add_player(bob.clone(), false);
+        room.set_ready(&alice, true);
+        room.set_ready(&bob, true);
+        room.start_game(&alice);
+
+        // March back and forth next to the capital so the path outlasts the disconnect
+        let (capital, next) = {
+            let sim = room.sim.read();
+            let capital = sim.cells.iter()
+                .position(|cell| cell.terrain == Terrain::Capital && cell.owner_id == Some(alice.id()))
+                .unwrap();
+            let next = crate::map::neighbors(capital, sim.width, sim.height)
+                .find(|&id| sim.cells[id].terrain != Terrain::Mountain)
+                .unwrap();
+            (capital as u32, next as u32)
+        };
+        let path = Path::new(vec![capital, next, capital, next, capital, next]);
+        room.queue_input(Input::UpdatePaths { player_id: alice.id(), paths: HashMap::from([(0, path)]) });
+        room.tick().await;
+
+        // While disconnected the player stays in the game and their path keeps going
+        assert!(alice.disconnect(alice.connection()));
+        room.tick().await;
+        assert!(room.players.read().contains_key(&alice.id()));
+        assert!(room.sim.read().is_alive(alice.id()));
+        assert_eq!(room.sim.read().players[&alice.id()].paths[&0].valid_until, 2);
+
+        let connection = alice.rebind(&human("Alice"));
+        room.resume_player(&alice);
+        assert_eq!(alice.connection(), connection);
+        assert!(*alice.connected.read());
+        room.tick().await;
+        assert_eq!(room.sim.read().players[&alice.id()].paths[&0].valid_until, 3);
+    }
+
+    #[test]
+    fn host_is_passed_on_and_kicked_players_stay_out() {
+        let room = room(2);
+        let (alice, bob, carol) = (human("Alice"), human("Bob"), human("Carol"));
+        for player in [&alice, &bob, &carol] {
+            room.add_player(player.clone(), false);
+        }
+        assert!(room.is_host(alice.id()));
+
+        // Only the host can manage bots, kick or hand over the role
+        room.add_bot(&bob, BotDifficulty::Expansion);
+        assert!(room.bots.read().is_empty());
+        room.add_bot(&alice, BotDifficulty::Expansion);
+        assert_eq!(room.bots.read().len(), 1);
+        room.remove_bots(&bob);
+        assert_eq!(room.bots.read().len(), 1);
+        assert!(!room.kick(&bob, carol.id()));
+        room.transfer_host(&bob, carol.id());
+        assert!(room.is_host(alice.id()));
+
+        assert!(room.kick(&alice, bob.id()));
+        assert!(room.is_kicked(bob.id()));
+        room.remove_player(bob.id());
+
+        // Bots never become host, the next human to have joined does
+        room.remove_player(alice.id());
+        assert!(room.is_host(carol.id()));
+    }
+
+    #[test]
+    fn map_size_changes_in_the_config_apply_to_the_next_game() {
+        let shared_config = Arc::new(RwLock::new(Config { min_ready_players: 1, ..Config::default() }));
+        let room = Room::new("test".to_string(), shared_config.clone(), None);
+        let alice = human("Alice");
+        room.add_player(alice.clone(), false);
+        room.set_ready(&alice, true);
+
+        // A reload while waiting in the lobby still makes it into the game
+        shared_config.write().terrain_config.map_width = 15;
+        shared_config.write().terrain_config.map_height = 12;
+        room.start_game(&alice);
+        assert_eq!(*room.game_state.read(), GameState::InGame);
+        assert_eq!((room.sim.read().width, room.sim.read().height), (15, 12));
+        let view = room.map_view(alice.id());
+        assert_eq!((view.width, view.height), (15, 12));
+
+        // As does one during the game, once the next one is set up
+        shared_config.write().terrain_config.map_width = 25;
+        room.set_game_state(GameState::GameOver);
+        room.play_again(&alice);
+        assert_eq!((room.sim.read().width, room.sim.read().height), (25, 12));
+        assert_eq!(room.sim.read().cells.len(), 25 * 12);
+    }
+
+    #[test]
+    fn host_can_pick_a_hand_made_map() {
+        let room = room(2);
+        let (alice, bob) = (human("Alice"), human("Bob"));
+        room.add_player(alice.clone(), false);
+        room.add_player(bob.clone(), false);
+
+        let settings = GameSettings { map: Some("crossroads".to_string()), ..room.config.read().settings() };
+        room.set_settings(&alice, settings);
+        let map = CustomMap::load("crossroads").unwrap();
+        {
+            let sim = room.sim.read();
+            assert_eq!((sim.width, sim.height), (map.width, map.height));
+            // Both capitals went onto spawn slots
+            let capitals: Vec<usize> = (0..sim.cells.len()).filter(|&id| sim.cells[id].terrain == Terrain::Capital).collect();
+            assert_eq!(capitals.len(), 2);
+            assert!(capitals.iter().all(|id| map.spawns.contains(id)));
+        }
+
+        // Maps that do not exist fall back to generated ones
+        let settings = GameSettings { map: Some("../config".to_string()), ..room.config.read().settings() };
+        room.set_settings(&alice, settings);
+        assert_eq!(room.config.read().map, None);
+    }
+
+    #[test]
+    fn host_settings_apply_to_the_next_map_within_bounds() {
+        let room = room(2);
+        let (alice, bob) = (human("Alice"), human("Bob"));
+        room.add_player(alice.clone(), false);
+        room.add_player(bob.clone(), false);
+
+        let settings = GameSettings { map_width: 12, map_height: 1000, tick_ms: 250.0, ..room.config.read().settings() };
+        room.set_settings(&bob, settings.clone());
+        assert_ne!(room.config.read().tick_ms, 250.0);
+
+        room.set_settings(&alice, settings);
+        let bounds = room.config.read().settings_bounds.clone();
+        assert_eq!(room.config.read().tick_ms, 250.0);
+        let sim = room.sim.read();
+        assert_eq!((sim.width, sim.height), (12, bounds.max_map_size));
+        assert_eq!(sim.cells.len(), 12 * bounds.max_map_size);
+    }
+}
@@ -0,0 +1,37 @@
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+use uuid::Uuid;
+
+use crate::player::Player;
+use crate::replay::ReplayViewer;
+use crate::room::Room;
+
+/// A logged in player, kept alive across websocket reconnects until the grace period runs out.
+pub struct Session {
+    pub token: Uuid,
+    pub player: Arc<Player>,
+    pub room: RwLock<Option<Arc<Room>>>,
+    pub replay: RwLock<Option<Arc<ReplayViewer>>>,
+    pub uploads: RwLock<usize>,  // Maps uploaded from the editor so far
+}
+
+impl Session {
+    pub fn new(player: Arc<Player>) -> Self {
+        Self {
+            token: Uuid::new_v4(),
+            player,
+            room: RwLock::new(None),
+            replay: RwLock::new(None),
+            uploads: RwLock::new(0),
+        }
+    }
+
+    pub fn room(&self) -> Option<Arc<Room>> {
+        self.room.read().clone()
+    }
+
+    pub fn replay(&self) -> Option<Arc<ReplayViewer>> {
+        self.replay.read().clone()
+    }
+}
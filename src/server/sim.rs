@@ -0,0 +1,791 @@
+//! The game rules, free of networking and locking so they can run anywhere:
+//! in a room, when re-playing a recorded game, or offline to try out settings.
+//!
+//! A `GameSim` only changes through `step`, which applies the inputs given to it
+//! and advances the game by one tick. Given the same starting state and inputs
+//! it always produces the same result.
+
+use std::collections::{BTreeMap, HashMap};
+
+use generals::shared::path::{Path, SplitMode};
+use generals::shared::Terrain;
+use uuid::Uuid;
+
+use crate::config::{Config, GrowthTable};
+use crate::map::{distance, neighbors, passable_regions, Cell};
+
+/// Something a player does to the game, applied at the start of the next step and recorded in replays.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub enum Input {
+    UpdatePaths { player_id: Uuid, paths: HashMap<u32, Path> },
+    CancelPath { player_id: Uuid, path_id: u32 },
+    ClearPaths { player_id: Uuid },
+    UndoStep { player_id: Uuid, path_id: u32 },
+    Leave { player_id: Uuid },
+}
+
+/// What happened during a step that the outside world may want to know about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    // A path's army moved on, `valid_until` is the index of the tile it is now on
+    MovementConfirmed { player_id: Uuid, path_id: u32, valid_until: u32 },
+    // The player's capital was taken
+    PlayerDefeated { player_id: Uuid, by: Option<Uuid> },
+    PlayerLeft { player_id: Uuid },
+    // Only one player or team still holds capitals, the winners are everyone still alive
+    GameOver { winners: Vec<Uuid> },
+}
+
+/// The parts of the config that decide how a game plays out.
+#[derive(Debug, Clone)]
+pub struct Rules {
+    pub growth: GrowthTable,
+    pub desert_attrition: f32,
+}
+
+impl From<&Config> for Rules {
+    fn from(config: &Config) -> Self {
+        Self {
+            growth: config.growth.clone(),
+            desert_attrition: config.desert_attrition,
+        }
+    }
+}
+
+/// Someone taking part in the game, spectators are not.
+#[derive(Debug, Clone)]
+pub struct SimPlayer {
+    pub paths: HashMap<u32, Path>,
+    pub alive: bool,
+    pub team: Option<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GameSim {
+    pub width: usize,
+    pub height: usize,
+    pub cells: Vec<Cell>,
+    // Where capitals go on hand-made maps, generated maps leave it empty
+    pub spawns: Vec<usize>,
+    // Ordered by id so every step processes the players in the same order
+    pub players: BTreeMap<Uuid, SimPlayer>,
+    // Number of steps taken, growth rules are timed by it
+    pub tick: u32,
+    pub rules: Rules,
+}
+
+impl GameSim {
+    pub fn new(width: usize, height: usize, cells: Vec<Cell>, rules: Rules) -> Self {
+        Self {
+            width,
+            height,
+            cells,
+            spawns: Vec::new(),
+            players: BTreeMap::new(),
+            tick: 0,
+            rules,
+        }
+    }
+
+    pub fn add_player(&mut self, player_id: Uuid, team: Option<u8>) {
+        self.players.insert(player_id, SimPlayer { paths: HashMap::new(), alive: true, team });
+    }
+
+    pub fn set_team(&mut self, player_id: Uuid, team: Option<u8>) {
+        if let Some(player) = self.players.get_mut(&player_id) {
+            player.team = team;
+        }
+    }
+
+    /// True for a player and themselves, and for two players on the same team.
+    pub fn allied(&self, a: Uuid, b: Uuid) -> bool {
+        let team = |player_id| self.players.get(&player_id).and_then(|player: &SimPlayer| player.team);
+        a == b || team(a).is_some_and(|team_a| team(b) == Some(team_a))
+    }
+
+    /// Everyone whose tiles `player_id` can see through: the player and their teammates.
+    pub fn vision_of(&self, player_id: Uuid) -> Vec<Uuid> {
+        let mut allies: Vec<Uuid> = self.players.keys()
+            .copied()
+            .filter(|&other| self.allied(player_id, other))
+            .collect();
+        if allies.is_empty() {
+            allies.push(player_id);
+        }
+        allies
+    }
+
+    /// Takes the player out of the game. Their land becomes neutral and their capital a city.
+    pub fn remove_player(&mut self, player_id: Uuid) {
+        self.players.remove(&player_id);
+        for cell in self.cells.iter_mut().filter(|cell| cell.owner_id == Some(player_id)) {
+            cell.owner_id = None;
+            if cell.terrain == Terrain::Capital {
+                cell.terrain = Terrain::City;
+            }
+        }
+    }
+
+    pub fn is_alive(&self, player_id: Uuid) -> bool {
+        self.players.get(&player_id).is_some_and(|player| player.alive)
+    }
+
+    /// Applies `inputs` in order, then moves every path one tile along and grows the owned tiles.
+    pub fn step(&mut self, inputs: Vec<Input>) -> Vec<Event> {
+        let mut events = Vec::new();
+        self.tick += 1;
+
+        for input in inputs {
+            self.apply_input(input, &mut events);
+        }
+
+        let player_ids: Vec<Uuid> = self.players.keys().copied().collect();
+        for player_id in player_ids {
+            let mut path_ids: Vec<u32> = self.players[&player_id].paths.keys().copied().collect();
+            path_ids.sort_unstable();
+
+            for path_id in path_ids {
+                let Some(path) = self.players.get_mut(&player_id).and_then(|player| player.paths.get_mut(&path_id)) else {
+                    continue;
+                };
+                if path.valid_until as usize + 1 >= path.tile_ids.len() {
+                    continue;
+                }
+
+                let attacking_id = path.tile_ids[path.valid_until as usize] as usize;
+                let defending_id = path.tile_ids[path.valid_until as usize + 1] as usize;
+                let split = path.split_at(path.valid_until);
+
+                // The path always moves on, but only fights from tiles the player still holds
+                path.valid_until += 1;
+                let valid_until = path.valid_until;
+                if self.cells[attacking_id].owner_id == Some(player_id) {
+                    self.tile_battle(attacking_id, defending_id, split, &mut events);
+                }
+
+                events.push(Event::MovementConfirmed { player_id, path_id, valid_until });
+            }
+        }
+
+        self.tick_growth();
+        events
+    }
+
+    /// Applies a single input, also used for inputs that arrive between games.
+    pub fn apply_input(&mut self, input: Input, events: &mut Vec<Event>) {
+        match input {
+            Input::UpdatePaths { player_id, paths: updated } => {
+                let Some(player) = self.players.get_mut(&player_id) else {
+                    return;
+                };
+                for (id, mut path) in updated {
+                    // Existing paths keep their progress, new ones start at the beginning
+                    path.valid_until = player.paths.get(&id).map_or(0, |existing| existing.valid_until);
+                    player.paths.insert(id, path);
+                }
+            }
+            Input::CancelPath { player_id, path_id } => {
+                if let Some(player) = self.players.get_mut(&player_id) {
+                    player.paths.remove(&path_id);
+                }
+            }
+            Input::ClearPaths { player_id } => {
+                if let Some(player) = self.players.get_mut(&player_id) {
+                    player.paths.clear();
+                }
+            }
+            Input::UndoStep { player_id, path_id } => {
+                // Tiles the army already reached cannot be taken back
+                if let Some(path) = self.players.get_mut(&player_id).and_then(|player| player.paths.get_mut(&path_id))
+                    && path.tile_ids.len() > path.valid_until as usize + 1 {
+                    path.pop();
+                }
+            }
+            Input::Leave { player_id } => {
+                self.remove_player(player_id);
+                events.push(Event::PlayerLeft { player_id });
+                // Everyone else may have left
+                self.check_game_over(events);
+            }
+        }
+    }
+
+    /// Ends the game once all remaining capitals belong to one player or team.
+    fn check_game_over(&self, events: &mut Vec<Event>) {
+        let owners: Vec<Uuid> = self.cells.iter()
+            .filter(|cell| cell.terrain == Terrain::Capital)
+            .filter_map(|cell| cell.owner_id)
+            .collect();
+        if let Some(&first) = owners.first()
+            && owners.iter().all(|&owner| self.allied(first, owner)) {
+            let winners = self.players.iter()
+                .filter(|&(&player_id, player)| player.alive && self.allied(first, player_id))
+                .map(|(&player_id, _)| player_id)
+                .collect();
+            events.push(Event::GameOver { winners });
+        }
+    }
+
+    /// Applies every growth rule that is due on the current tick to the owned tiles.
+    fn tick_growth(&mut self) {
+        for cell in self.cells.iter_mut() {
+            if cell.owner_id.is_none() {
+                continue;
+            }
+
+            if let Some(rule) = self.rules.growth.rule(cell.terrain)
+                && rule.interval > 0
+                && self.tick.is_multiple_of(rule.interval) {
+                cell.troops = cell.troops.saturating_add_signed(rule.amount);
+            }
+
+            // Tiles drained of all their troops are lost
+            if cell.troops == 0 {
+                cell.owner_id = None;
+            }
+        }
+    }
+
+    fn tile_battle(&mut self, attacking_id: usize, defending_id: usize, split: SplitMode, events: &mut Vec<Event>) {
+        let allied = match (self.cells[attacking_id].owner_id, self.cells[defending_id].owner_id) {
+            (Some(attacker), Some(defender)) => self.allied(attacker, defender),
+            _ => false,
+        };
+        let cells = &mut self.cells;
+
+        // Get the current state
+        let attacking_troops = cells[attacking_id].troops;
+        let attacking_owner = cells[attacking_id].owner_id;
+        let defending_troops = cells[defending_id].troops;
+        let defending_owner = cells[defending_id].owner_id;
+        let defending_terrain = cells[defending_id].terrain;
+
+        // Don't do anything if attacking tile has 1 or fewer troops
+        if attacking_troops <= 1 {
+            return;
+        }
+
+        // Cannot move onto mountains
+        if defending_terrain == Terrain::Mountain {
+            return;
+        }
+
+        // Calculate the battle outcome, at least 1 troop always stays behind
+        let mut moving_troops = split.moving_troops(attacking_troops);
+        if moving_troops == 0 {
+            return;
+        }
+        cells[attacking_id].troops -= moving_troops;
+
+        // Crossing the desert costs part of the army, at least one troop always arrives
+        if defending_terrain == Terrain::Desert {
+            let attrition = self.rules.desert_attrition.clamp(0.0, 1.0);
+            let lost = (moving_troops as f32 * attrition).floor() as u32;
+            moving_troops = (moving_troops - lost).max(1);
+        }
+
+        if allied {
+            // Own and allied tiles combine troops, the tile keeps its owner
+            cells[defending_id].troops += moving_troops;
+        } else if moving_troops > defending_troops {
+            // Attacker wins
+            cells[defending_id].troops = moving_troops - defending_troops;
+            cells[defending_id].owner_id = attacking_owner;
+
+            // If this was a capital capture, transfer all territory and convert to city
+            if cells[defending_id].terrain == Terrain::Capital
+                && let Some(defeated_player) = defending_owner {
+                if let Some(player) = self.players.get_mut(&defeated_player) {
+                    player.alive = false;
+                }
+                events.push(Event::PlayerDefeated { player_id: defeated_player, by: attacking_owner });
+
+                // Transfer all territory from the defeated player to the attacker
+                for cell in cells.iter_mut() {
+                    if cell.owner_id == Some(defeated_player) {
+                        cell.owner_id = attacking_owner;
+                    }
+                }
+                // Convert captured capital to a city
+                cells[defending_id].terrain = Terrain::City;
+
+                self.check_game_over(events);
+            }
+        } else {
+            // Defender wins or ties
+            cells[defending_id].troops -= moving_troops;
+            // Remove ownership if troops hit 0
+            if cells[defending_id].troops == 0 {
+                cells[defending_id].owner_id = None;
+            }
+        }
+    }
+
+    /// Places a capital for `player` on a plain, unowned tile that can reach every other capital,
+    /// at least `min_distance` steps away from them. Maps with spawn slots get their capitals on a
+    /// free slot that keeps the distance, while there is one. Returns false if no tile keeps the distance.
+    pub fn place_capital(&mut self, player: Uuid, min_distance: usize, rng: &mut impl rand::Rng) -> bool {
+        let (width, height) = (self.width, self.height);
+        let cells = &mut self.cells;
+        let capitals: Vec<usize> = cells.iter()
+            .enumerate()
+            .filter(|(_, cell)| cell.terrain == Terrain::Capital)
+            .map(|(id, _)| id)
+            .collect();
+        let far_enough = |id: usize| capitals.iter().all(|&capital| distance(id, capital, width) >= min_distance);
+
+        let free_spawns: Vec<usize> = self.spawns.iter()
+            .copied()
+            .filter(|&id| cells[id].owner_id.is_none() && far_enough(id))
+            .collect();
+        if !free_spawns.is_empty() {
+            let cell_id = free_spawns[rng.gen_range(0..free_spawns.len())];
+            cells[cell_id] = Cell {
+                terrain: Terrain::Capital,
+                troops: 1,
+                owner_id: Some(player),
+            };
+            return true;
+        }
+
+        let regions = passable_regions(cells, width, height);
+
+        // New capitals go where the existing ones are, or into the biggest area on an empty map
+        let anchors: Vec<usize> = if capitals.is_empty() {
+            (0..cells.len()).collect()
+        } else {
+            capitals.clone()
+        };
+        let mut region_sizes = HashMap::new();
+        for region in anchors.iter().filter_map(|&id| regions[id]) {
+            *region_sizes.entry(region).or_insert(0) += 1;
+        }
+        let Some(region) = region_sizes.into_iter()
+            .max_by_key(|&(region, count)| (count, std::cmp::Reverse(region)))
+            .map(|(region, _)| region) else {
+            return false;
+        };
+
+        // Only plain tiles nobody owns or borders, so spawns never replace special terrain
+        let candidates: Vec<usize> = (0..cells.len())
+            .filter(|&id| regions[id] == Some(region))
+            .filter(|&id| cells[id].terrain == Terrain::Default && cells[id].owner_id.is_none())
+            .filter(|&id| neighbors(id, width, height).all(|n| cells[n].owner_id.is_none()))
+            .filter(|&id| far_enough(id))
+            .collect();
+        if candidates.is_empty() {
+            return false;
+        }
+        let cell_id = candidates[rng.gen_range(0..candidates.len())];
+
+        // Set the cell as a capital with initial troops
+        cells[cell_id] = Cell {
+            terrain: Terrain::Capital,
+            troops: 1,  // Start with 1 troop
+            owner_id: Some(player),
+        };
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GrowthRule;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    // Walks along the row first, then along the column
+    fn route(from: usize, to: usize, width: usize) -> Vec<u32> {
+        let (mut x, mut y) = (from % width, from / width);
+        let mut tiles = vec![from as u32];
+        while x != to % width {
+            x = if x < to % width { x + 1 } else { x - 1 };
+            tiles.push((y * width + x) as u32);
+        }
+        while y != to / width {
+            y = if y < to / width { y + 1 } else { y - 1 };
+            tiles.push((y * width + x) as u32);
+        }
+        tiles
+    }
+
+    #[test]
+    fn capitals_are_spread_out_and_reachable() {
+        // A 12x12 map with a mountain wall sealing off the rightmost two columns
+        let (width, height) = (12, 12);
+        let mut cells = vec![Cell::default(); width * height];
+        for y in 0..height {
+            cells[y * width + 9].terrain = Terrain::Mountain;
+        }
+        cells[0].terrain = Terrain::Swamp;
+
+        for seed in 0..50 {
+            let mut sim = GameSim::new(width, height, cells.clone(), Rules::from(&Config::default()));
+            let mut rng = StdRng::seed_from_u64(seed);
+            for _ in 0..3 {
+                assert!(sim.place_capital(Uuid::new_v4(), 5, &mut rng));
+            }
+
+            let cells = &sim.cells;
+            let capitals: Vec<usize> = (0..cells.len()).filter(|&id| cells[id].terrain == Terrain::Capital).collect();
+            assert_eq!(capitals.len(), 3);
+            assert_eq!(cells[0].terrain, Terrain::Swamp);
+
+            let regions = passable_regions(cells, width, height);
+            for &a in &capitals {
+                assert!(a % width < 9, "capital placed behind the wall");
+                assert_eq!(regions[a], regions[capitals[0]]);
+                for &b in capitals.iter().filter(|&&b| b != a) {
+                    assert!(distance(a, b, width) >= 5);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn full_maps_never_squeeze_capitals_together() {
+        let (width, height) = (10, 10);
+        let mut sim = GameSim::new(width, height, vec![Cell::default(); width * height], Rules::from(&Config::default()));
+        // Slots 0 and 1 are too close to both be used
+        sim.spawns = vec![0, 1, 99];
+        let mut rng = StdRng::seed_from_u64(5);
+
+        let mut placed = 0;
+        while sim.place_capital(Uuid::new_v4(), 6, &mut rng) {
+            placed += 1;
+            assert!(placed < 100, "capitals keep being placed");
+        }
+
+        let capitals: Vec<usize> = (0..sim.cells.len()).filter(|&id| sim.cells[id].terrain == Terrain::Capital).collect();
+        assert_eq!(capitals.len(), placed);
+        assert!(placed >= 3);
+        assert!(!(capitals.contains(&0) && capitals.contains(&1)));
+        for &a in &capitals {
+            for &b in capitals.iter().filter(|&&b| b != a) {
+                assert!(distance(a, b, width) >= 6, "capitals {a} and {b} are too close");
+            }
+        }
+    }
+
+    #[test]
+    fn same_inputs_give_the_same_game() {
+        let run = || {
+            let mut sim = GameSim::new(8, 8, vec![Cell::default(); 64], Rules::from(&Config::default()));
+            let mut rng = StdRng::seed_from_u64(7);
+            let players = [Uuid::from_u128(1), Uuid::from_u128(2)];
+            for &player_id in &players {
+                sim.add_player(player_id, None);
+                sim.place_capital(player_id, 4, &mut rng);
+            }
+
+            // Both players head for the other's capital once their armies have grown a bit
+            let capital = |player_id| sim.cells.iter().position(|cell| cell.owner_id == Some(player_id)).unwrap();
+            let (a, b) = (capital(players[0]), capital(players[1]));
+            let mut events = Vec::new();
+            for tick in 0..200 {
+                let inputs = if tick == 50 {
+                    vec![
+                        Input::UpdatePaths { player_id: players[0], paths: HashMap::from([(0, Path::new(route(a, b, 8)))]) },
+                        Input::UpdatePaths { player_id: players[1], paths: HashMap::from([(0, Path::new(route(b, a, 8)))]) },
+                    ]
+                } else {
+                    Vec::new()
+                };
+                events.extend(sim.step(inputs));
+            }
+            (sim.cells.iter().map(|cell| (cell.terrain, cell.troops, cell.owner_id)).collect::<Vec<_>>(), events)
+        };
+
+        let (cells, events) = run();
+        assert!(events.iter().any(|event| matches!(event, Event::MovementConfirmed { .. })));
+        assert_eq!((cells, events), run());
+    }
+
+    const ALICE: Uuid = Uuid::from_u128(1);
+    const BOB: Uuid = Uuid::from_u128(2);
+    const CAROL: Uuid = Uuid::from_u128(3);
+
+    // Rules without any growth, so only the battles change troop counts
+    fn no_growth() -> Rules {
+        let off = GrowthRule { interval: 0, amount: 0 };
+        Rules {
+            growth: GrowthTable { capital: off, city: off, plain: off, desert: off, swamp: off },
+            desert_attrition: 0.25,
+        }
+    }
+
+    fn cell(terrain: Terrain, troops: u32, owner_id: Option<Uuid>) -> Cell {
+        Cell { terrain, troops, owner_id }
+    }
+
+    // A single row of cells with Alice, Bob and Carol taking part
+    fn row(cells: Vec<Cell>, rules: Rules) -> GameSim {
+        let mut sim = GameSim::new(cells.len(), 1, cells, rules);
+        for player_id in [ALICE, BOB, CAROL] {
+            sim.add_player(player_id, None);
+        }
+        sim
+    }
+
+    // Replaces the player's paths with one along `tiles` and steps once
+    fn attack(sim: &mut GameSim, player_id: Uuid, tiles: Vec<u32>) -> Vec<Event> {
+        attack_with(sim, player_id, Path::new(tiles))
+    }
+
+    fn attack_with(sim: &mut GameSim, player_id: Uuid, path: Path) -> Vec<Event> {
+        sim.step(vec![
+            Input::ClearPaths { player_id },
+            Input::UpdatePaths { player_id, paths: HashMap::from([(0, path)]) },
+        ])
+    }
+
+    fn troops(sim: &GameSim) -> Vec<(u32, Option<Uuid>)> {
+        sim.cells.iter().map(|cell| (cell.troops, cell.owner_id)).collect()
+    }
+
+    #[test]
+    fn attacker_with_more_troops_takes_the_tile() {
+        let mut sim = row(vec![
+            cell(Terrain::Default, 10, Some(ALICE)),
+            cell(Terrain::Default, 4, Some(BOB)),
+        ], no_growth());
+
+        let events = attack(&mut sim, ALICE, vec![0, 1]);
+        assert_eq!(troops(&sim), vec![(1, Some(ALICE)), (5, Some(ALICE))]);
+        assert_eq!(events, vec![Event::MovementConfirmed { player_id: ALICE, path_id: 0, valid_until: 1 }]);
+    }
+
+    #[test]
+    fn ties_and_losses_go_to_the_defender() {
+        // Nine attackers against nine defenders wipe each other out and leave the tile neutral
+        let mut sim = row(vec![
+            cell(Terrain::Default, 10, Some(ALICE)),
+            cell(Terrain::Default, 9, Some(BOB)),
+        ], no_growth());
+        attack(&mut sim, ALICE, vec![0, 1]);
+        assert_eq!(troops(&sim), vec![(1, Some(ALICE)), (0, None)]);
+
+        // Too few attackers only thin the defence out
+        let mut sim = row(vec![
+            cell(Terrain::Default, 5, Some(ALICE)),
+            cell(Terrain::City, 40, None),
+        ], no_growth());
+        attack(&mut sim, ALICE, vec![0, 1]);
+        assert_eq!(troops(&sim), vec![(1, Some(ALICE)), (36, None)]);
+        assert_eq!(sim.cells[1].terrain, Terrain::City);
+    }
+
+    #[test]
+    fn moving_onto_your_own_tile_merges_the_armies() {
+        let mut sim = row(vec![
+            cell(Terrain::Default, 6, Some(ALICE)),
+            cell(Terrain::Default, 3, Some(ALICE)),
+            cell(Terrain::Default, 1, Some(ALICE)),
+        ], no_growth());
+
+        attack(&mut sim, ALICE, vec![0, 1, 2]);
+        assert_eq!(troops(&sim), vec![(1, Some(ALICE)), (8, Some(ALICE)), (1, Some(ALICE))]);
+        sim.step(Vec::new());
+        assert_eq!(troops(&sim), vec![(1, Some(ALICE)), (1, Some(ALICE)), (8, Some(ALICE))]);
+    }
+
+    #[test]
+    fn armies_stay_put_when_they_cannot_move() {
+        let mut sim = row(vec![
+            cell(Terrain::Default, 1, Some(ALICE)),
+            cell(Terrain::Default, 0, None),
+            cell(Terrain::Default, 20, Some(ALICE)),
+            cell(Terrain::Mountain, 0, None),
+        ], no_growth());
+
+        // A lone troop never leaves, mountains cannot be entered
+        attack(&mut sim, ALICE, vec![0, 1]);
+        attack(&mut sim, ALICE, vec![2, 3]);
+        assert_eq!(troops(&sim), vec![(1, Some(ALICE)), (0, None), (20, Some(ALICE)), (0, None)]);
+
+        // Paths starting on someone else's tile move on without fighting
+        let events = attack(&mut sim, BOB, vec![2, 1]);
+        assert_eq!(sim.cells[1].owner_id, None);
+        assert_eq!(events, vec![Event::MovementConfirmed { player_id: BOB, path_id: 0, valid_until: 1 }]);
+    }
+
+    #[test]
+    fn paths_can_be_shortened_cancelled_and_cleared() {
+        let mut sim = row(vec![
+            cell(Terrain::Default, 20, Some(ALICE)),
+            cell(Terrain::Default, 0, None),
+            cell(Terrain::Default, 0, None),
+            cell(Terrain::Default, 0, None),
+            cell(Terrain::Default, 0, None),
+        ], no_growth());
+        let update = |path_id, tiles| Input::UpdatePaths { player_id: ALICE, paths: HashMap::from([(path_id, Path::new(tiles))]) };
+
+        // Undoing takes back the planned steps, but never the tiles the army already reached
+        sim.step(vec![update(0, vec![0, 1, 2, 3])]);
+        sim.step(vec![Input::UndoStep { player_id: ALICE, path_id: 0 }; 3]);
+        assert_eq!(sim.players[&ALICE].paths[&0].tile_ids, vec![0, 1]);
+        assert_eq!(sim.cells[2].owner_id, None);
+
+        // A cancelled path stops before its next move
+        sim.step(vec![update(1, vec![1, 2, 3]), Input::CancelPath { player_id: ALICE, path_id: 1 }]);
+        assert!(!sim.players[&ALICE].paths.contains_key(&1));
+        assert_eq!(sim.cells[2].owner_id, None);
+
+        sim.step(vec![update(2, vec![1, 2])]);
+        assert_eq!(sim.cells[2].owner_id, Some(ALICE));
+
+        // Clearing drops every path at once
+        sim.step(vec![update(3, vec![2, 3, 4]), Input::ClearPaths { player_id: ALICE }]);
+        assert!(sim.players[&ALICE].paths.is_empty());
+        assert_eq!(sim.cells[3].owner_id, None);
+    }
+
+    #[test]
+    fn split_modes_and_desert_attrition_decide_how_many_arrive() {
+        let mut sim = row(vec![
+            cell(Terrain::Default, 20, Some(ALICE)),
+            cell(Terrain::Default, 0, None),
+            cell(Terrain::Default, 20, Some(BOB)),
+            cell(Terrain::Desert, 0, None),
+            cell(Terrain::Default, 2, Some(CAROL)),
+            cell(Terrain::Desert, 0, None),
+        ], no_growth());
+
+        attack_with(&mut sim, ALICE, Path::with_split(vec![0, 1], SplitMode::Half));
+        assert_eq!(troops(&sim)[..2], [(10, Some(ALICE)), (10, Some(ALICE))]);
+
+        // A quarter of the 19 troops is lost in the desert, rounded down
+        attack(&mut sim, BOB, vec![2, 3]);
+        assert_eq!(troops(&sim)[2..4], [(1, Some(BOB)), (15, Some(BOB))]);
+
+        // At least one troop always makes it across
+        sim.rules.desert_attrition = 1.0;
+        attack(&mut sim, CAROL, vec![4, 5]);
+        assert_eq!(troops(&sim)[4..], [(1, Some(CAROL)), (1, Some(CAROL))]);
+    }
+
+    #[test]
+    fn every_step_moves_with_its_own_split() {
+        let mut sim = row(vec![
+            cell(Terrain::Default, 20, Some(ALICE)),
+            cell(Terrain::Default, 0, None),
+            cell(Terrain::Default, 0, None),
+            cell(Terrain::Default, 0, None),
+        ], no_growth());
+
+        let mut path = Path::new(vec![0]);
+        path.push(1, SplitMode::AllButOne);
+        path.push(2, SplitMode::Half);
+        path.push(3, SplitMode::Fixed(2));
+        attack_with(&mut sim, ALICE, path);
+        sim.step(Vec::new());
+        sim.step(Vec::new());
+        assert_eq!(troops(&sim), vec![(1, Some(ALICE)), (10, Some(ALICE)), (7, Some(ALICE)), (2, Some(ALICE))]);
+    }
+
+    #[test]
+    fn taking_a_capital_takes_the_whole_empire() {
+        let mut sim = row(vec![
+            cell(Terrain::Capital, 30, Some(ALICE)),
+            cell(Terrain::Capital, 5, Some(BOB)),
+            cell(Terrain::Default, 7, Some(BOB)),
+            cell(Terrain::Capital, 3, Some(CAROL)),
+        ], no_growth());
+
+        let events = attack(&mut sim, ALICE, vec![0, 1]);
+        assert_eq!(sim.cells[1], cell(Terrain::City, 24, Some(ALICE)));
+        assert_eq!(sim.cells[2].owner_id, Some(ALICE));
+        assert!(!sim.is_alive(BOB));
+        assert!(sim.is_alive(CAROL));
+
+        // Carol still has her capital, so the game goes on
+        assert_eq!(events, vec![
+            Event::PlayerDefeated { player_id: BOB, by: Some(ALICE) },
+            Event::MovementConfirmed { player_id: ALICE, path_id: 0, valid_until: 1 },
+        ]);
+    }
+
+    #[test]
+    fn taking_the_last_other_capital_ends_the_game() {
+        let mut sim = row(vec![
+            cell(Terrain::Capital, 10, Some(ALICE)),
+            cell(Terrain::Default, 1, Some(ALICE)),
+            cell(Terrain::Capital, 4, Some(BOB)),
+        ], no_growth());
+
+        // Neutral cities left over from earlier captures do not count
+        sim.cells.push(cell(Terrain::City, 10, None));
+        sim.width += 1;
+
+        attack(&mut sim, ALICE, vec![0, 1, 2]);
+        let events = sim.step(Vec::new());
+        assert!(events.contains(&Event::GameOver { winners: vec![ALICE] }));
+        assert_eq!(troops(&sim)[..3], [(1, Some(ALICE)), (1, Some(ALICE)), (5, Some(ALICE))]);
+    }
+
+    #[test]
+    fn the_last_player_left_wins() {
+        let mut sim = row(vec![
+            cell(Terrain::Capital, 10, Some(ALICE)),
+            cell(Terrain::Capital, 4, Some(BOB)),
+            cell(Terrain::Capital, 4, Some(CAROL)),
+        ], no_growth());
+
+        // Bob's capital turns into a neutral city, Carol is still in the game
+        let events = sim.step(vec![Input::Leave { player_id: BOB }]);
+        assert_eq!(events, vec![Event::PlayerLeft { player_id: BOB }]);
+        assert_eq!(sim.cells[1], cell(Terrain::City, 4, None));
+
+        let events = sim.step(vec![Input::Leave { player_id: CAROL }]);
+        assert_eq!(events, vec![
+            Event::PlayerLeft { player_id: CAROL },
+            Event::GameOver { winners: vec![ALICE] },
+        ]);
+    }
+
+    #[test]
+    fn growth_follows_the_table_and_empty_tiles_are_lost() {
+        let mut sim = row(vec![
+            cell(Terrain::Capital, 1, Some(ALICE)),
+            cell(Terrain::Default, 1, Some(ALICE)),
+            cell(Terrain::Swamp, 2, Some(ALICE)),
+            cell(Terrain::City, 5, None),
+        ], Rules::from(&Config::default()));
+
+        // Default rules: capitals +1 every 5 ticks, plains +1 every 25, swamps -1 every 10,
+        // unowned tiles never change
+        for _ in 0..10 {
+            sim.step(Vec::new());
+        }
+        assert_eq!(troops(&sim), vec![(3, Some(ALICE)), (1, Some(ALICE)), (1, Some(ALICE)), (5, None)]);
+
+        for _ in 0..15 {
+            sim.step(Vec::new());
+        }
+        assert_eq!(troops(&sim), vec![(6, Some(ALICE)), (2, Some(ALICE)), (0, None), (5, None)]);
+    }
+
+    #[test]
+    fn teammates_reinforce_each_other_and_win_together() {
+        let mut sim = row(vec![
+            cell(Terrain::Capital, 12, Some(ALICE)),
+            cell(Terrain::Default, 2, Some(BOB)),
+            cell(Terrain::Capital, 1, Some(BOB)),
+            cell(Terrain::Capital, 4, Some(CAROL)),
+        ], no_growth());
+        sim.set_team(ALICE, Some(1));
+        sim.set_team(BOB, Some(1));
+        assert_eq!(sim.vision_of(ALICE), vec![ALICE, BOB]);
+        assert_eq!(sim.vision_of(CAROL), vec![CAROL]);
+
+        // Moving onto a teammate's tile adds to their army instead of fighting it
+        attack(&mut sim, ALICE, vec![0, 1]);
+        assert_eq!(troops(&sim)[..2], [(1, Some(ALICE)), (13, Some(BOB))]);
+
+        // Bob's capital survives, so taking Carol's leaves only the team standing
+        attack(&mut sim, BOB, vec![1, 2, 3]);
+        let events = sim.step(Vec::new());
+        assert_eq!(troops(&sim)[1..], [(1, Some(BOB)), (1, Some(BOB)), (8, Some(BOB))]);
+        assert!(events.contains(&Event::GameOver { winners: vec![ALICE, BOB] }));
+    }
+}
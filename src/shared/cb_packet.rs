@@ -1,37 +1,147 @@
+use std::collections::HashMap;
+
 use uuid::Uuid;
 
-use crate::shared::{game_state::GameState, Color, PlayerView};
+use crate::shared::{game_state::GameState, path::PathRejectReason, sb_packet::ChatScope, settings::{GameSettings, SettingsBounds}, Color, PlayerView, RoomView};
 
-use super::map::MapView;
+use super::compact::CompactMapDelta;
+use super::map::{Cell, MapView};
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub enum CBPacket {
     LoginAccepted(LoginAccepted),
+    ResumeRejected,
     MapSync(MapSync),
+    MapDelta(MapDelta),
     SyncPlayers(SyncPlayers),
 
     SetGameState(GameState),
+    // Seed the room's current map was generated from
+    MapSeed(u64),
     MovementConfirmed(MovementConfirmed),
+    PathRejected(PathRejected),
+
+    RoomList(RoomList),
+    RoomJoined(RoomJoined),
+    RoomSettings(RoomSettings),
+    // The host removed the player from their room
+    Kicked,
+
+    ReplayList(ReplayList),
+    ReplayStarted(ReplayStarted),
+    ReplayFrame(ReplayFrame),
+
+    ChatMessage(ChatMessage),
+    ChatRejected(ChatRejectReason),
+
+    // Name of the map the player uploaded from the editor
+    MapUploaded(String),
+    // Why an uploaded map was not saved
+    MapRejected(String),
 }
 
+/// Full view of the map, sent on joining and whenever the client asks to resync.
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub struct MapSync {
     pub map: MapView,
+    // Number of the last map update this snapshot includes
+    pub tick: u32,
+}
+
+/// Cells that changed since the previous update. Updates are numbered consecutively,
+/// a client that misses one has to ask for a new snapshot with `SBPacket::GiveMeMap`.
+/// Sent over the wire as a `CompactMapDelta`.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(into = "CompactMapDelta", try_from = "CompactMapDelta")]
+pub struct MapDelta {
+    pub tick: u32,
+    pub changed: HashMap<usize, Cell>,
+    pub removed: Vec<usize>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub struct LoginAccepted {
     pub player_id: Uuid,
     pub color: Color,
+    pub session_token: Uuid,
+    // Limits on map sizes and settings on this server, also used by the map editor
+    pub settings_bounds: SettingsBounds,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub struct SyncPlayers {
     pub players: Vec<PlayerView>,
+    // Player who can start the game, kick players and hand the role on
+    pub host: Option<Uuid>,
+}
+
+/// Settings the room's next game is played with, and what its host may change them to.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct RoomSettings {
+    pub settings: GameSettings,
+    pub bounds: SettingsBounds,
+    // Hand-made maps the host can pick from
+    pub maps: Vec<String>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub struct MovementConfirmed {
     pub path_id: u32,
     pub valid_until: u32,
-}
\ No newline at end of file
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct PathRejected {
+    pub path_id: u32,
+    pub reason: PathRejectReason,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct RoomList {
+    pub rooms: Vec<RoomView>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct RoomJoined {
+    pub name: String,
+}
+
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct ReplayList {
+    pub names: Vec<String>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct ReplayStarted {
+    pub name: String,
+    pub seed: u64,
+    pub total_ticks: u32,
+    pub players: Vec<PlayerView>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct ReplayFrame {
+    pub tick: u32,
+    pub playing: bool,
+    pub speed: f32,
+    pub map: MapView,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct ChatMessage {
+    pub scope: ChatScope,
+    pub sender: Uuid,
+    pub name: String,
+    pub text: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatRejectReason {
+    Empty,
+    TooLong,
+    // Too many messages in a short time
+    RateLimited,
+    // Team chat without a team, or room chat outside a room
+    NoAudience,
+}
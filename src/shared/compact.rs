@@ -0,0 +1,314 @@
+//! Compact wire encoding for map views and deltas.
+//!
+//! Instead of a map of cell ids to cells with a full `Uuid` per owned cell, cells are sent
+//! as parallel arrays in id order, with the owners replaced by an index into a small table
+//! of the players that appear in the packet.
+
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use super::cb_packet::MapDelta;
+use super::map::{Cell, MapView};
+use super::terrain::Terrain;
+
+/// Owner index meaning the cell has no owner
+const NO_OWNER: u16 = 0;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CompactMapView {
+    pub width: u32,
+    pub height: u32,
+    // Bit per cell, set for the cells present in the view
+    pub visible: Vec<u8>,
+    // Bit per cell, set for the visible cells that are covered by fog of war
+    pub fog: Vec<u8>,
+    pub cells: CompactCells,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CompactMapDelta {
+    pub tick: u32,
+    pub changed_ids: Vec<u32>,
+    pub changed_fog: Vec<bool>,
+    pub cells: CompactCells,
+    pub removed: Vec<u32>,
+}
+
+/// Parallel arrays with one entry per cell
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CompactCells {
+    // Players referenced by `owners`, index 1 is the first entry
+    pub players: Vec<Uuid>,
+    pub terrain: Vec<u8>,
+    pub troops: Vec<u32>,
+    pub owners: Vec<u16>,
+}
+
+#[derive(Debug)]
+pub struct DecodeError(&'static str);
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid compact map: {}", self.0)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+fn terrain_to_u8(terrain: Terrain) -> u8 {
+    match terrain {
+        Terrain::Default => 0,
+        Terrain::Mountain => 1,
+        Terrain::Swamp => 2,
+        Terrain::Desert => 3,
+        Terrain::Capital => 4,
+        Terrain::City => 5,
+    }
+}
+
+fn terrain_from_u8(value: u8) -> Result<Terrain, DecodeError> {
+    Ok(match value {
+        0 => Terrain::Default,
+        1 => Terrain::Mountain,
+        2 => Terrain::Swamp,
+        3 => Terrain::Desert,
+        4 => Terrain::Capital,
+        5 => Terrain::City,
+        _ => return Err(DecodeError("unknown terrain")),
+    })
+}
+
+fn get_bit(bits: &[u8], index: usize) -> bool {
+    bits.get(index / 8).is_some_and(|byte| byte & (1 << (index % 8)) != 0)
+}
+
+fn set_bit(bits: &mut [u8], index: usize) {
+    bits[index / 8] |= 1 << (index % 8);
+}
+
+impl CompactCells {
+    // Owner indices are 16 bits, enough for a different owner on every cell of the largest maps
+    fn push(&mut self, cell: &Cell) {
+        let owner = match cell.owner_id {
+            None => NO_OWNER,
+            Some(owner_id) => match self.players.iter().position(|&id| id == owner_id) {
+                Some(index) => index as u16 + 1,
+                None => {
+                    self.players.push(owner_id);
+                    self.players.len() as u16
+                }
+            },
+        };
+
+        self.terrain.push(terrain_to_u8(cell.terrain));
+        self.troops.push(cell.troops);
+        self.owners.push(owner);
+    }
+
+    fn get(&self, index: usize, fog_of_war: bool) -> Result<Cell, DecodeError> {
+        let (Some(&terrain), Some(&troops), Some(&owner)) =
+            (self.terrain.get(index), self.troops.get(index), self.owners.get(index)) else {
+            return Err(DecodeError("cell arrays too short"));
+        };
+
+        let owner_id = match owner {
+            NO_OWNER => None,
+            index => Some(*self.players.get(index as usize - 1).ok_or(DecodeError("unknown owner"))?),
+        };
+
+        Ok(Cell { terrain: terrain_from_u8(terrain)?, troops, owner_id, fog_of_war })
+    }
+
+    fn len(&self) -> usize {
+        self.terrain.len()
+    }
+}
+
+impl From<MapView> for CompactMapView {
+    fn from(map: MapView) -> Self {
+        let total_cells = map.width * map.height;
+        let mut visible = vec![0; total_cells.div_ceil(8)];
+        let mut fog = vec![0; total_cells.div_ceil(8)];
+        let mut cells = CompactCells::default();
+
+        // Walk the cells in id order so the arrays line up with the bitset
+        let mut ids: Vec<usize> = map.cells.keys().copied().filter(|&id| id < total_cells).collect();
+        ids.sort_unstable();
+        for id in ids {
+            let cell = &map.cells[&id];
+            set_bit(&mut visible, id);
+            if cell.fog_of_war {
+                set_bit(&mut fog, id);
+            }
+            cells.push(cell);
+        }
+
+        Self {
+            width: map.width as u32,
+            height: map.height as u32,
+            visible,
+            fog,
+            cells,
+        }
+    }
+}
+
+impl TryFrom<CompactMapView> for MapView {
+    type Error = DecodeError;
+
+    fn try_from(compact: CompactMapView) -> Result<Self, Self::Error> {
+        let (width, height) = (compact.width as usize, compact.height as usize);
+        let mut cells = HashMap::with_capacity(compact.cells.len());
+
+        let mut index = 0;
+        for id in (0..width * height).filter(|&id| get_bit(&compact.visible, id)) {
+            cells.insert(id, compact.cells.get(index, get_bit(&compact.fog, id))?);
+            index += 1;
+        }
+        if index != compact.cells.len() {
+            return Err(DecodeError("more cells than visible bits"));
+        }
+
+        Ok(MapView { width, height, cells })
+    }
+}
+
+impl From<MapDelta> for CompactMapDelta {
+    fn from(delta: MapDelta) -> Self {
+        let mut changed: Vec<(usize, Cell)> = delta.changed.into_iter().collect();
+        changed.sort_unstable_by_key(|(id, _)| *id);
+
+        let mut cells = CompactCells::default();
+        for (_, cell) in &changed {
+            cells.push(cell);
+        }
+
+        Self {
+            tick: delta.tick,
+            changed_ids: changed.iter().map(|(id, _)| *id as u32).collect(),
+            changed_fog: changed.iter().map(|(_, cell)| cell.fog_of_war).collect(),
+            cells,
+            removed: delta.removed.into_iter().map(|id| id as u32).collect(),
+        }
+    }
+}
+
+impl TryFrom<CompactMapDelta> for MapDelta {
+    type Error = DecodeError;
+
+    fn try_from(compact: CompactMapDelta) -> Result<Self, Self::Error> {
+        if compact.changed_ids.len() != compact.cells.len() || compact.changed_fog.len() != compact.cells.len() {
+            return Err(DecodeError("changed cell arrays differ in length"));
+        }
+
+        let changed = compact.changed_ids.iter()
+            .zip(&compact.changed_fog)
+            .enumerate()
+            .map(|(index, (&id, &fog_of_war))| Ok((id as usize, compact.cells.get(index, fog_of_war)?)))
+            .collect::<Result<_, DecodeError>>()?;
+
+        Ok(MapDelta {
+            tick: compact.tick,
+            changed,
+            removed: compact.removed.into_iter().map(|id| id as usize).collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_view() -> MapView {
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        let cell = |terrain, troops, owner_id, fog_of_war| Cell { terrain, troops, owner_id, fog_of_war };
+
+        MapView {
+            width: 5,
+            height: 3,
+            cells: HashMap::from([
+                (0, cell(Terrain::Capital, 12, Some(alice), false)),
+                (1, cell(Terrain::Default, 3, Some(alice), false)),
+                (2, cell(Terrain::Desert, 0, None, false)),
+                (7, cell(Terrain::Mountain, 0, None, true)),
+                (9, cell(Terrain::City, 41, None, false)),
+                (13, cell(Terrain::Swamp, 2, Some(bob), false)),
+                (14, cell(Terrain::Capital, 70_000, Some(bob), false)),
+            ]),
+        }
+    }
+
+    #[test]
+    fn map_view_round_trips() {
+        let view = sample_view();
+        let compact = CompactMapView::from(view.clone());
+        assert_eq!(compact.cells.players.len(), 2);
+        assert_eq!(compact.cells.len(), view.cells.len());
+
+        let decoded = MapView::try_from(compact).unwrap();
+        assert_eq!((decoded.width, decoded.height), (view.width, view.height));
+        assert_eq!(decoded.cells, view.cells);
+    }
+
+    #[test]
+    fn map_view_round_trips_through_bincode() {
+        let view = sample_view();
+        let bytes = bincode::serialize(&view).unwrap();
+        let decoded: MapView = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(decoded.cells, view.cells);
+
+        // On a full board the compact form must be considerably smaller than the plain map
+        let owners = [Some(Uuid::new_v4()), Some(Uuid::new_v4()), None];
+        let board = MapView {
+            width: 30,
+            height: 30,
+            cells: (0..900)
+                .map(|id| (id, Cell { terrain: Terrain::Default, troops: id as u32, owner_id: owners[id % 3], fog_of_war: false }))
+                .collect(),
+        };
+        let compact = bincode::serialize(&board).unwrap();
+        let plain = bincode::serialize(&(board.width, board.height, &board.cells)).unwrap();
+        assert!(compact.len() * 4 < plain.len());
+    }
+
+    #[test]
+    fn map_delta_round_trips() {
+        let view = sample_view();
+        let delta = MapDelta { tick: 3, changed: view.cells.clone(), removed: vec![4, 11] };
+
+        let bytes = bincode::serialize(&delta).unwrap();
+        let decoded: MapDelta = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(decoded.tick, 3);
+        assert_eq!(decoded.changed, view.cells);
+        assert_eq!(decoded.removed, vec![4, 11]);
+    }
+
+    #[test]
+    fn corrupt_input_is_rejected() {
+        let mut compact = CompactMapView::from(sample_view());
+        compact.cells.owners[0] = 9;
+        assert!(MapView::try_from(compact.clone()).is_err());
+
+        compact.cells.owners[0] = NO_OWNER;
+        compact.cells.terrain.pop();
+        assert!(MapView::try_from(compact).is_err());
+    }
+
+    #[test]
+    fn hundreds_of_owners_keep_their_cells() {
+        // More owners than fit in a byte, each cell owned by someone else
+        let board = MapView {
+            width: 20,
+            height: 20,
+            cells: (0..400)
+                .map(|id| (id, Cell { terrain: Terrain::Default, troops: 1, owner_id: Some(Uuid::new_v4()), fog_of_war: false }))
+                .collect(),
+        };
+
+        let compact = CompactMapView::from(board.clone());
+        assert_eq!(compact.cells.players.len(), 400);
+        let decoded = MapView::try_from(compact).unwrap();
+        assert_eq!(decoded.cells, board.cells);
+    }
+}
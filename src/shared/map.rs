@@ -1,20 +1,90 @@
 use std::collections::HashMap;
 
+use super::cb_packet::MapDelta;
+use super::compact::CompactMapView;
 use super::terrain::Terrain;
 
 use uuid::Uuid;
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+/// Sent over the wire as a `CompactMapView`
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(into = "CompactMapView", try_from = "CompactMapView")]
 pub struct MapView {
     pub width: usize,
     pub height: usize,
     pub cells: HashMap<usize, Cell>,
 }
 
+impl MapView {
+    /// Lists the cells that differ between this view and `newer`, so that applying the
+    /// delta to this view turns it into `newer`.
+    pub fn diff(&self, newer: &MapView, tick: u32) -> MapDelta {
+        let changed = newer.cells.iter()
+            .filter(|(id, cell)| self.cells.get(id) != Some(cell))
+            .map(|(id, cell)| (*id, cell.clone()))
+            .collect();
+        let removed = self.cells.keys()
+            .filter(|id| !newer.cells.contains_key(id))
+            .copied()
+            .collect();
+        MapDelta { tick, changed, removed }
+    }
+
+    pub fn apply_delta(&mut self, delta: MapDelta) {
+        for id in delta.removed {
+            self.cells.remove(&id);
+        }
+        self.cells.extend(delta.changed);
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct Cell {
     pub terrain: Terrain,
     pub troops: u32,
     pub owner_id: Option<Uuid>,
     pub fog_of_war: bool,
-}
\ No newline at end of file
+}
+
+/// The cells sharing an edge with `id`.
+pub fn neighbors(id: usize, width: usize, height: usize) -> impl Iterator<Item = usize> {
+    let (x, y) = (id % width, id / width);
+    [
+        (x > 0).then(|| id - 1),
+        (x + 1 < width).then(|| id + 1),
+        (y > 0).then(|| id - width),
+        (y + 1 < height).then(|| id + width),
+    ].into_iter().flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(terrain: Terrain, troops: u32) -> Cell {
+        Cell { terrain, troops, owner_id: None, fog_of_war: false }
+    }
+
+    #[test]
+    fn applying_a_diff_reproduces_the_newer_view() {
+        let older = MapView {
+            width: 3,
+            height: 1,
+            cells: HashMap::from([(0, cell(Terrain::Default, 1)), (1, cell(Terrain::City, 40))]),
+        };
+        let newer = MapView {
+            width: 3,
+            height: 1,
+            cells: HashMap::from([(0, cell(Terrain::Default, 2)), (2, cell(Terrain::Swamp, 0))]),
+        };
+
+        let delta = older.diff(&newer, 7);
+        assert_eq!(delta.tick, 7);
+        assert_eq!(delta.changed.len(), 2);
+        assert_eq!(delta.removed, vec![1]);
+
+        let mut patched = older.clone();
+        patched.apply_delta(delta);
+        assert_eq!(patched.cells, newer.cells);
+    }
+}
@@ -0,0 +1,141 @@
+//! Format of hand-made maps, as stored by the server and written by the map editor:
+//!
+//! ```toml
+//! city_troops = 40  # Garrison of every city not listed under [[cities]]
+//! rows = [
+//!     "@..#..",
+//!     ".c.#..",
+//!     "..dd.@",
+//! ]
+//!
+//! [[cities]]
+//! x = 1
+//! y = 1
+//! troops = 25
+//! ```
+//!
+//! Every row is one line of the map with one character per tile: `.` plain, `#` mountain,
+//! `d` desert, `s` swamp, `c` city and `@` a plain tile where a capital can spawn.
+
+use super::map::MapView;
+use super::terrain::Terrain;
+
+/// Garrison of cities when a map file does not say otherwise
+pub const DEFAULT_CITY_TROOPS: u32 = 40;
+
+/// A map file as written on disk.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MapFile {
+    #[serde(default = "default_city_troops")]
+    pub city_troops: u32,
+    pub rows: Vec<String>,
+    #[serde(default)]
+    pub cities: Vec<CityGarrison>,
+}
+
+/// Troops on a city that differ from the map's `city_troops`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CityGarrison {
+    pub x: usize,
+    pub y: usize,
+    pub troops: u32,
+}
+
+fn default_city_troops() -> u32 {
+    DEFAULT_CITY_TROOPS
+}
+
+/// Terrain of a tile character, with `true` for spawn slots. `None` for unknown characters.
+pub fn tile_terrain(tile: char) -> Option<(Terrain, bool)> {
+    match tile {
+        '.' => Some((Terrain::Default, false)),
+        '#' => Some((Terrain::Mountain, false)),
+        'd' => Some((Terrain::Desert, false)),
+        's' => Some((Terrain::Swamp, false)),
+        'c' => Some((Terrain::City, false)),
+        '@' => Some((Terrain::Default, true)),
+        _ => None,
+    }
+}
+
+impl MapFile {
+    /// Turns a map view into a map file. Capitals mark spawn slots, ownership is left out.
+    pub fn from_view(map: &MapView) -> Self {
+        let mut rows = Vec::with_capacity(map.height);
+        let mut cities = Vec::new();
+        for y in 0..map.height {
+            let row = (0..map.width)
+                .map(|x| {
+                    let cell = map.cells.get(&(y * map.width + x));
+                    match cell.map(|cell| cell.terrain) {
+                        Some(Terrain::Mountain) => '#',
+                        Some(Terrain::Desert) => 'd',
+                        Some(Terrain::Swamp) => 's',
+                        Some(Terrain::Capital) => '@',
+                        Some(Terrain::City) => {
+                            let troops = cell.map_or(DEFAULT_CITY_TROOPS, |cell| cell.troops);
+                            if troops != DEFAULT_CITY_TROOPS {
+                                cities.push(CityGarrison { x, y, troops });
+                            }
+                            'c'
+                        }
+                        Some(Terrain::Default) | None => '.',
+                    }
+                })
+                .collect();
+            rows.push(row);
+        }
+
+        Self { city_troops: DEFAULT_CITY_TROOPS, rows, cities }
+    }
+
+    /// Writes the file with one row per line, so it stays readable and easy to edit by hand.
+    pub fn to_toml(&self) -> String {
+        let mut out = format!("city_troops = {}\nrows = [\n", self.city_troops);
+        for row in &self.rows {
+            // Rows that are not plain tile characters would need escaping
+            let row: String = row.chars().map(|tile| if tile_terrain(tile).is_some() { tile } else { '.' }).collect();
+            out.push_str(&format!("    \"{row}\",\n"));
+        }
+        out.push_str("]\n");
+        for city in &self.cities {
+            out.push_str(&format!("\n[[cities]]\nx = {}\ny = {}\ntroops = {}\n", city.x, city.y, city.troops));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::shared::map::Cell;
+
+    fn cell(terrain: Terrain, troops: u32) -> Cell {
+        Cell { terrain, troops, owner_id: None, fog_of_war: false }
+    }
+
+    #[test]
+    fn map_views_are_written_as_map_files() {
+        let map = MapView {
+            width: 3,
+            height: 2,
+            cells: HashMap::from([
+                (0, cell(Terrain::Capital, 0)),
+                (1, cell(Terrain::Mountain, 0)),
+                (2, cell(Terrain::City, 40)),
+                (3, cell(Terrain::Desert, 0)),
+                (4, cell(Terrain::City, 15)),
+                (5, cell(Terrain::Swamp, 0)),
+            ]),
+        };
+
+        let file = MapFile::from_view(&map);
+        assert_eq!(file.rows, vec!["@#c", "dcs"]);
+        assert_eq!(file.cities, vec![CityGarrison { x: 1, y: 1, troops: 15 }]);
+
+        let reread: MapFile = toml::from_str(&file.to_toml()).unwrap();
+        assert_eq!(reread, file);
+    }
+}
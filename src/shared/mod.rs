@@ -1,11 +1,16 @@
 pub mod map;
+pub mod compact;
 pub mod terrain;
 pub mod cb_packet;
 pub mod sb_packet;
 pub mod packet;
 pub mod player;
 pub mod path;
+pub mod pathfinding;
 pub mod game_state;
+pub mod room;
+pub mod settings;
+pub mod map_file;
 
 pub use map::MapView;
 pub use terrain::Terrain;
@@ -13,6 +18,7 @@ pub use cb_packet::CBPacket;
 pub use sb_packet::SBPacket;
 pub use packet::{read_len_prefixed, write_len_prefixed};
 pub use player::PlayerView;
+pub use room::RoomView;
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy)]
 pub struct Color {
@@ -2,27 +2,91 @@
 pub struct Path {
     pub tile_ids: Vec<u32>,
     pub valid_until: u32,
+    // How much of the army each step takes along, `splits[i]` for the step leaving `tile_ids[i]`.
+    // Steps without an entry move all but one.
+    pub splits: Vec<SplitMode>,
+}
+
+/// How many troops a move takes along
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SplitMode {
+    #[default]
+    AllButOne,
+    Half,
+    // Up to this many troops, always leaving at least one behind
+    Fixed(u32),
+}
+
+impl SplitMode {
+    /// Troops that leave a tile holding `troops`
+    pub fn moving_troops(self, troops: u32) -> u32 {
+        let available = troops.saturating_sub(1);
+        match self {
+            SplitMode::AllButOne => available,
+            SplitMode::Half => troops / 2,
+            SplitMode::Fixed(count) => count.min(available),
+        }
+    }
 }
 
 impl Path {
     pub fn new(tile_ids: Vec<u32>) -> Self {
-        Self { tile_ids, valid_until: 0 }
+        Self { tile_ids, valid_until: 0, splits: Vec::new() }
+    }
+
+    /// A path that moves with the same split on every step.
+    pub fn with_split(tile_ids: Vec<u32>, split: SplitMode) -> Self {
+        let splits = vec![split; tile_ids.len().saturating_sub(1)];
+        Self { splits, ..Self::new(tile_ids) }
+    }
+
+    /// Split used for the step leaving `tile_ids[step]`
+    pub fn split_at(&self, step: u32) -> SplitMode {
+        self.splits.get(step as usize).copied().unwrap_or_default()
+    }
+
+    /// Extends the path by one tile, moving there with `split`.
+    pub fn push(&mut self, tile_id: u32, split: SplitMode) {
+        if let Some(step) = self.tile_ids.len().checked_sub(1) {
+            self.splits.resize(step, SplitMode::AllButOne);
+            self.splits.push(split);
+        }
+        self.tile_ids.push(tile_id);
+    }
+
+    /// Takes back the last tile along with the split of the step leading to it.
+    pub fn pop(&mut self) -> Option<u32> {
+        let tile_id = self.tile_ids.pop()?;
+        self.splits.truncate(self.tile_ids.len().saturating_sub(1));
+        Some(tile_id)
     }
 
     pub fn remove_front(&mut self, n: usize) {
         self.tile_ids.drain(0..n.min(self.tile_ids.len()));
+        self.splits.drain(0..n.min(self.splits.len()));
     }
 
     pub fn is_valid(&self, width: usize, height: usize) -> bool {
+        self.validate(width, height, usize::MAX).is_ok()
+    }
+
+    /// Checks that the path stays on the map, only takes steps to adjacent tiles and
+    /// is at most `max_length` tiles long.
+    pub fn validate(&self, width: usize, height: usize, max_length: usize) -> Result<(), PathRejectReason> {
         // Path must have at least one tile
         if self.tile_ids.is_empty() {
-            return false;
+            return Err(PathRejectReason::Empty);
+        }
+
+        // Splits only go with steps, so there is never more than one per tile
+        if self.tile_ids.len() > max_length || self.splits.len() >= self.tile_ids.len() {
+            return Err(PathRejectReason::TooLong);
         }
 
         // All tile IDs must be within map bounds
         let max_id = (width * height) as u32;
         if self.tile_ids.iter().any(|&id| id >= max_id) {
-            return false;
+            return Err(PathRejectReason::OutOfBounds);
         }
 
         // Check each consecutive pair of tiles for adjacency
@@ -42,12 +106,75 @@ impl Path {
 
             // Tiles must differ by exactly 1 in either x or y, but not both
             if !((x_diff == 1 && y_diff == 0) || (x_diff == 0 && y_diff == 1)) {
-                return false;
+                return Err(PathRejectReason::NotAdjacent);
             }
         }
 
-        true
+        Ok(())
+    }
+}
+
+/// Why the server refused a path
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathRejectReason {
+    Empty,
+    TooLong,
+    OutOfBounds,
+    NotAdjacent,
+    // The player already has as many paths going as they are allowed
+    TooManyPaths,
+    // New paths have to start on a tile the player owns
+    NotOwned,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_reports_why_a_path_is_illegal() {
+        // 4x3 map
+        let validate = |tile_ids: Vec<u32>| Path::new(tile_ids).validate(4, 3, 5);
+
+        assert_eq!(validate(vec![0, 1, 5, 9]), Ok(()));
+        assert_eq!(validate(vec![]), Err(PathRejectReason::Empty));
+        assert_eq!(validate(vec![0, 1, 2, 3, 7, 11]), Err(PathRejectReason::TooLong));
+        assert_eq!(validate(vec![11, 12]), Err(PathRejectReason::OutOfBounds));
+        assert_eq!(validate(vec![0, 5]), Err(PathRejectReason::NotAdjacent));
+        // Stepping off the right edge must not wrap around to the next row
+        assert_eq!(validate(vec![3, 4]), Err(PathRejectReason::NotAdjacent));
+    }
+
+    #[test]
+    fn split_modes_always_leave_a_troop_behind() {
+        assert_eq!(SplitMode::AllButOne.moving_troops(10), 9);
+        assert_eq!(SplitMode::Half.moving_troops(10), 5);
+        assert_eq!(SplitMode::Half.moving_troops(3), 1);
+        assert_eq!(SplitMode::Fixed(4).moving_troops(10), 4);
+        assert_eq!(SplitMode::Fixed(40).moving_troops(10), 9);
+        assert_eq!(SplitMode::Half.moving_troops(1), 0);
     }
 
+    #[test]
+    fn every_step_keeps_its_own_split() {
+        let mut path = Path::new(vec![0]);
+        path.push(1, SplitMode::Half);
+        path.push(2, SplitMode::AllButOne);
+        path.push(3, SplitMode::Fixed(5));
+        assert_eq!(path.split_at(0), SplitMode::Half);
+        assert_eq!(path.split_at(2), SplitMode::Fixed(5));
+        assert_eq!(path.split_at(3), SplitMode::AllButOne);
 
-}
\ No newline at end of file
+        // Undoing a step drops its split, the next step gets a new one
+        assert_eq!(path.pop(), Some(3));
+        path.push(7, SplitMode::Half);
+        assert_eq!(path.splits, vec![SplitMode::Half, SplitMode::AllButOne, SplitMode::Half]);
+
+        path.remove_front(1);
+        assert_eq!((path.tile_ids.clone(), path.split_at(0)), (vec![1, 2, 7], SplitMode::AllButOne));
+
+        // A split per tile is one too many
+        path.splits.push(SplitMode::Half);
+        assert_eq!(path.validate(8, 1, 10), Err(PathRejectReason::TooLong));
+    }
+}
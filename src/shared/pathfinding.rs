@@ -0,0 +1,110 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use super::map::{neighbors, MapView};
+use super::terrain::Terrain;
+
+/// Finds the shortest route from `from` to `to` using only what the view shows.
+/// Mountains are never entered, cells outside the view are assumed walkable and
+/// swamps are only used when `avoid_swamps` is off or the target itself is a swamp.
+/// The returned tile ids start with `from` and end with `to`.
+pub fn find_path(view: &MapView, from: usize, to: usize, avoid_swamps: bool) -> Option<Vec<u32>> {
+    let total_cells = view.width * view.height;
+    if from >= total_cells || to >= total_cells {
+        return None;
+    }
+
+    let passable = |id: usize| match view.cells.get(&id).map(|cell| cell.terrain) {
+        Some(Terrain::Mountain) => false,
+        Some(Terrain::Swamp) => !avoid_swamps || id == to,
+        _ => true,
+    };
+    if !passable(to) {
+        return None;
+    }
+
+    let heuristic = |id: usize| {
+        let (x, y) = (id % view.width, id / view.width);
+        x.abs_diff(to % view.width) + y.abs_diff(to / view.width)
+    };
+
+    // Ties are broken on the cell id so the same view always yields the same route
+    let mut open = BinaryHeap::from([Reverse((heuristic(from), from))]);
+    let mut came_from: HashMap<usize, usize> = HashMap::new();
+    let mut cost = HashMap::from([(from, 0)]);
+
+    while let Some(Reverse((_, current))) = open.pop() {
+        if current == to {
+            let mut route = vec![to as u32];
+            let mut cell = to;
+            while let Some(&previous) = came_from.get(&cell) {
+                route.push(previous as u32);
+                cell = previous;
+            }
+            route.reverse();
+            return Some(route);
+        }
+
+        let next_cost = cost[&current] + 1;
+        for next in neighbors(current, view.width, view.height).filter(|&next| passable(next)) {
+            if cost.get(&next).is_none_or(|&known| next_cost < known) {
+                cost.insert(next, next_cost);
+                came_from.insert(next, current);
+                open.push(Reverse((next_cost + heuristic(next), next)));
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::map::Cell;
+
+    // Builds a view from rows of '.', '#' (mountain), '~' (swamp) and '?' (not visible)
+    fn view(rows: &[&str]) -> MapView {
+        let width = rows[0].len();
+        let mut cells = HashMap::new();
+        for (id, tile) in rows.concat().chars().enumerate() {
+            let terrain = match tile {
+                '#' => Terrain::Mountain,
+                '~' => Terrain::Swamp,
+                '?' => continue,
+                _ => Terrain::Default,
+            };
+            cells.insert(id, Cell { terrain, troops: 0, owner_id: None, fog_of_war: false });
+        }
+        MapView { width, height: rows.len(), cells }
+    }
+
+    #[test]
+    fn routes_around_mountains_and_swamps() {
+        let map = view(&[
+            ".#..#",
+            ".#.#.",
+            ".~.#.",
+            "...#.",
+        ]);
+
+        // Through the swamp when allowed, around it otherwise
+        assert_eq!(find_path(&map, 0, 2, false), Some(vec![0, 5, 10, 11, 12, 7, 2]));
+        assert_eq!(find_path(&map, 0, 2, true).map(|route| route.len()), Some(9));
+
+        // The right column is walled off by mountains
+        assert_eq!(find_path(&map, 0, 19, false), None);
+        assert_eq!(find_path(&map, 0, 1, false), None);
+        assert_eq!(find_path(&map, 0, 0, false), Some(vec![0]));
+    }
+
+    #[test]
+    fn cells_outside_the_view_are_assumed_walkable() {
+        let map = view(&[
+            "..#",
+            "..?",
+            "...",
+        ]);
+        assert_eq!(find_path(&map, 1, 5, false), Some(vec![1, 4, 5]));
+    }
+}
@@ -7,4 +7,22 @@ pub struct PlayerView {
     pub name: String,
     pub color: Color,
     pub alive: bool,
+    pub spectator: bool,
+    pub connected: bool,
+    pub bot: Option<BotDifficulty>,
+    // Players on the same team share vision and win together, None plays alone
+    pub team: Option<u8>,
+    // Ready for the game to start, bots always are
+    pub ready: bool,
+}
+
+/// Number of teams players can pick from in the lobby
+pub const MAX_TEAMS: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum BotDifficulty {
+    // Grabs as much neutral land as it can and only fights when cornered
+    Expansion,
+    // Expands until it spots an enemy, then goes for them
+    Aggressive,
 }
\ No newline at end of file
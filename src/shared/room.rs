@@ -0,0 +1,9 @@
+use super::game_state::GameState;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RoomView {
+    pub name: String,
+    pub player_count: usize,
+    pub spectator_count: usize,
+    pub game_state: GameState,
+}
@@ -1,14 +1,47 @@
 use std::collections::HashMap;
 
-use crate::shared::{path::Path, Color};
+use uuid::Uuid;
+
+use crate::shared::{map_file::MapFile, path::Path, player::BotDifficulty, settings::GameSettings, Color};
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub enum SBPacket {
     Login(Login),
+    Resume(Resume),
     GiveMeMap,
     UpdatePaths(UpdatePaths),
+    CancelPath(CancelPath),
+    ClearPaths,
+    UndoStep(UndoStep),
     StartGame,
     PlayAgain,
+
+    ListRooms,
+    CreateRoom(CreateRoom),
+    JoinRoom(JoinRoom),
+    // Back to the room list
+    LeaveRoom,
+
+    SetSpectator(SetSpectator),
+    FollowPlayer(FollowPlayer),
+    SetTeam(SetTeam),
+    SetReady(SetReady),
+
+    // Only the room's host may do these
+    KickPlayer(KickPlayer),
+    TransferHost(TransferHost),
+    SetSettings(GameSettings),
+
+    AddBot(AddBot),
+    RemoveBots,
+
+    ListReplays,
+    WatchReplay(WatchReplay),
+    ReplayControl(ReplayControl),
+
+    Chat(Chat),
+
+    UploadMap(UploadMap),
 }
 
 
@@ -18,7 +51,110 @@ pub struct Login {
     pub color_bid: Option<Color>,
 }
 
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Resume {
+    pub session_token: Uuid,
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub struct UpdatePaths {
     pub paths: HashMap<u32, Path>,
-}
\ No newline at end of file
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct CancelPath {
+    pub path_id: u32,
+}
+
+/// Takes back the last tile of a path, as long as the army has not reached it yet
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct UndoStep {
+    pub path_id: u32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct CreateRoom {
+    pub name: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct JoinRoom {
+    pub name: String,
+    pub spectate: bool,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct SetSpectator {
+    pub spectator: bool,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct FollowPlayer {
+    pub player_id: Option<Uuid>,
+}
+
+/// Picks a team in the lobby, `None` to play alone
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct SetTeam {
+    pub team: Option<u8>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct SetReady {
+    pub ready: bool,
+}
+
+/// Removes a player from the room and keeps them from coming back
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct KickPlayer {
+    pub player_id: Uuid,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct TransferHost {
+    pub player_id: Uuid,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct AddBot {
+    pub difficulty: BotDifficulty,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct WatchReplay {
+    pub name: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub enum ReplayControl {
+    Play,
+    Pause,
+    Seek(u32),
+    SetSpeed(f32),
+    Perspective(Option<Uuid>),
+    Stop,
+}
+
+/// Saves a map from the editor on the server and opens a room to play it in
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct UploadMap {
+    pub name: String,
+    pub map: MapFile,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Chat {
+    pub scope: ChatScope,
+    pub text: String,
+}
+
+/// Who gets to read a chat message
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatScope {
+    // Everyone who is choosing a room
+    Lobby,
+    // Everyone in the sender's room, spectators included
+    All,
+    // The sender's teammates, or the other spectators when watching
+    Team,
+}
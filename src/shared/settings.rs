@@ -0,0 +1,122 @@
+/// Options the host of a room can change in the lobby, snapshotted into the game when it starts.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct GameSettings {
+    pub map_width: usize,
+    pub map_height: usize,
+    pub mountain_density: f32,
+    pub desert_density: f32,
+    pub swamp_density: f32,
+    pub city_density: f32,
+    pub tick_ms: f64,
+    pub fow_mountains: bool,
+    pub fow_swamps: bool,
+    pub disguise_cities_as_mountains: bool,
+    // Hand-made map to play on instead of a generated one, its size and terrain replace the settings above
+    pub map: Option<String>,
+}
+
+/// What the server lets hosts choose from.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct SettingsBounds {
+    pub min_map_size: usize,
+    pub max_map_size: usize,
+    pub max_density: f32,        // For each terrain type on its own
+    pub max_total_density: f32,  // For all terrain types together, the rest of the map stays plain
+    pub min_tick_ms: f64,
+    pub max_tick_ms: f64,
+}
+
+impl Default for SettingsBounds {
+    fn default() -> Self {
+        Self {
+            min_map_size: 10,
+            max_map_size: 60,
+            max_density: 0.4,
+            max_total_density: 0.6,
+            min_tick_ms: 100.0,
+            max_tick_ms: 2000.0,
+        }
+    }
+}
+
+impl GameSettings {
+    /// Brings every value within `bounds`.
+    pub fn clamp(mut self, bounds: &SettingsBounds) -> Self {
+        let min_size = bounds.min_map_size.min(bounds.max_map_size);
+        self.map_width = self.map_width.clamp(min_size, bounds.max_map_size);
+        self.map_height = self.map_height.clamp(min_size, bounds.max_map_size);
+
+        let max_density = bounds.max_density.max(0.0);
+        let mut total = 0.0;
+        for density in [&mut self.mountain_density, &mut self.desert_density, &mut self.swamp_density, &mut self.city_density] {
+            *density = if density.is_finite() { density.clamp(0.0, max_density) } else { 0.0 };
+            total += *density;
+        }
+        // Scale all of them down together when the map would be too crowded
+        let max_total_density = bounds.max_total_density.max(0.0);
+        if total > max_total_density {
+            let scale = max_total_density / total;
+            self.mountain_density *= scale;
+            self.desert_density *= scale;
+            self.swamp_density *= scale;
+            self.city_density *= scale;
+        }
+
+        self.tick_ms = if self.tick_ms.is_nan() {
+            bounds.max_tick_ms
+        } else {
+            self.tick_ms.clamp(bounds.min_tick_ms.min(bounds.max_tick_ms), bounds.max_tick_ms)
+        };
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> GameSettings {
+        GameSettings {
+            map_width: 30,
+            map_height: 30,
+            mountain_density: 0.1,
+            desert_density: 0.1,
+            swamp_density: 0.1,
+            city_density: 0.05,
+            tick_ms: 500.0,
+            fow_mountains: false,
+            fow_swamps: false,
+            disguise_cities_as_mountains: true,
+            map: None,
+        }
+    }
+
+    #[test]
+    fn settings_within_bounds_are_kept() {
+        assert_eq!(settings().clamp(&SettingsBounds::default()), settings());
+    }
+
+    #[test]
+    fn settings_are_clamped_to_bounds() {
+        let bounds = SettingsBounds::default();
+        let clamped = GameSettings {
+            map_width: 1000,
+            map_height: 0,
+            mountain_density: f32::NAN,
+            desert_density: 0.9,
+            swamp_density: 0.4,
+            city_density: -1.0,
+            tick_ms: 1.0,
+            ..settings()
+        }.clamp(&bounds);
+
+        assert_eq!((clamped.map_width, clamped.map_height), (bounds.max_map_size, bounds.min_map_size));
+        assert_eq!(clamped.mountain_density, 0.0);
+        assert_eq!(clamped.city_density, 0.0);
+        // Deserts and swamps are both at the limit of 0.4, then share the total of 0.6
+        assert!((clamped.desert_density - 0.3).abs() < 1e-6);
+        assert!((clamped.swamp_density - 0.3).abs() < 1e-6);
+        assert_eq!(clamped.tick_ms, bounds.min_tick_ms);
+    }
+}